@@ -0,0 +1,117 @@
+#include <cerrno>
+#include <cmath>
+#include <cstdlib>
+#include <iostream>
+#include <string>
+#include <type_traits>
+#include <utility>
+
+#include <sys/socket.h>
+
+#include "rollbar.hpp"
+
+static_assert(!std::is_copy_constructible<rollbar::Client>::value, "Client is move-only");
+static_assert(!std::is_copy_assignable<rollbar::Client>::value, "Client is move-only");
+static_assert(std::is_nothrow_move_constructible<rollbar::Client>::value, "Client moves");
+
+// `assert` is compiled out of release builds.
+static void expect(bool condition, const char* what) {
+    if (!condition) {
+        std::cerr << "failed: " << what << std::endl;
+        std::exit(1);
+    }
+}
+
+// Nothing listens on the discard port, so items fail fast without leaving
+// the machine.
+static rollbar::ConfigCompat offline_config() {
+    rollbar::ConfigCompat config = {};
+
+    config.access_token = "token";
+    config.uri = "http://127.0.0.1:9";
+
+    return config;
+}
+
+static void missing_token_throws() {
+    rollbar::ConfigCompat config = {};
+
+    try {
+        rollbar::Client client(config);
+        expect(false, "expected an exception");
+    } catch (const rollbar::Exception& error) {
+        expect(error.code() == ROLLBAR_ERR_ARGUMENT, "error.code() == ROLLBAR_ERR_ARGUMENT");
+        expect(std::string(error.what()) == "access_token is required", "std::string(error.what()) == \"access_token is required\"");
+    }
+}
+
+static void invalid_extra_throws() {
+    rollbar::Client client(offline_config());
+
+    try {
+        client.info("hello", "{");
+        expect(false, "expected an exception");
+    } catch (const rollbar::Exception& error) {
+        expect(error.code() == ROLLBAR_ERR_ARGUMENT, "error.code() == ROLLBAR_ERR_ARGUMENT");
+    }
+}
+
+static void moves_transfer_ownership() {
+    rollbar::Client first(offline_config());
+    rollbar::Transport* transport = first.get();
+
+    rollbar::Client second(std::move(first));
+
+    expect(first.get() == nullptr, "first.get() == nullptr");
+    expect(second.get() == transport, "second.get() == transport");
+
+    try {
+        first.info("moved from");
+        expect(false, "expected an exception");
+    } catch (const rollbar::Exception& error) {
+        expect(error.code() == ROLLBAR_ERR_ARGUMENT, "error.code() == ROLLBAR_ERR_ARGUMENT");
+    }
+
+    rollbar::Client third(offline_config());
+    third = std::move(second);
+
+    expect(third.get() == transport, "third.get() == transport");
+}
+
+static void logs_are_queued() {
+    rollbar::Client client(offline_config());
+
+    client.log(rollbar::LevelCompat::Warning, "hello");
+    client.info("hello", "{\"answer\": 42}");
+    client.error(std::string("hello"));
+
+    try {
+        client.shutdown();
+        expect(false, "expected the offline items to fail");
+    } catch (const rollbar::Exception& error) {
+        expect(error.code() == ROLLBAR_ERR_TRANSPORT, "error.code() == ROLLBAR_ERR_TRANSPORT");
+    }
+
+    rollbar::StatsCompat stats = client.stats();
+    expect(stats.failed == 3, "stats.failed == 3");
+    expect(stats.queue_depth == 0, "stats.queue_depth == 0");
+}
+
+// The library must not replace libc or libm functions when linked statically.
+static void system_functions_are_not_shadowed() {
+    volatile double e = std::exp(1.0);
+    expect(std::log(e) == 1.0, "std::log(e) == 1.0");
+
+    errno = 0;
+    expect(::shutdown(-1, SHUT_RDWR) == -1 && errno == EBADF, "::shutdown(-1) fails with EBADF");
+}
+
+int main() {
+    system_functions_are_not_shadowed();
+    missing_token_throws();
+    invalid_extra_throws();
+    moves_transfer_ownership();
+    logs_are_queued();
+
+    std::cout << "ok" << std::endl;
+}
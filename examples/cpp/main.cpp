@@ -1,20 +1,34 @@
 #include <iostream>
-#include "rollbar.h"
+#include <stdexcept>
+#include "rollbar.hpp"
 
 int main() {
-    struct rollbar::ConfigCompat config;
+    rollbar::ConfigCompat config = {};
 
     config.access_token = "b5938ecbdb984aa091234644b0686c3d";
+    config.environment = "development";
+    config.crash_spool_dir = "rollbar-crashes";
 
-    rollbar::Transport* transport = nullptr;
+    try {
+        rollbar::Client client(config);
 
-    int code = rollbar::create_transport(config, &transport);
+        rollbar::rollbar_install_crash_handler(client.get(), "rollbar-crashes");
 
-    if (code != 0) {
-        return 1;
-    }
+        client.debug("hello from cpp");
+        client.info("hello with extra data", "{\"answer\": 42}");
+
+        try {
+            throw std::runtime_error("something went wrong");
+        } catch (const std::exception& error) {
+            rollbar::Trace* trace = rollbar::rollbar_trace_new("std::runtime_error", error.what());
 
-    rollbar::log(transport, rollbar::LevelCompat::Debug, "hello from cpp");
+            rollbar::rollbar_trace_capture(trace, 0);
+            rollbar::rollbar_send_trace(client.get(), rollbar::LevelCompat::Error, trace);
+        }
 
-    rollbar::shutdown(transport);
+        client.shutdown();
+    } catch (const rollbar::Exception& error) {
+        std::cerr << "rollbar: " << error.what() << std::endl;
+        return 1;
+    }
 }
@@ -20,7 +20,17 @@ const Home: NextPage = () => {
 
   useEffect(() => {
     const instance = Instance.fromConfig({
-      accessToken: process.env.POST_TOKEN
+      accessToken: process.env.POST_TOKEN,
+      captureUncaught: true,
+      captureUnhandledRejections: true,
+      persistQueue: true,
+      sourceMapEnabled: true,
+      autoInstrument: {
+        log: true,
+        network: true,
+        navigation: true,
+        dom: true
+      }
     });
 
     setRollbar(instance);
@@ -52,7 +62,9 @@ const Home: NextPage = () => {
     const extra = extraFields
       .reduce((extra, { key, value }) => ({ ...extra, [key]: value }), {});
 
-    rollbar[level](message, extra);
+    rollbar[level](message, extra)
+      .then((uuid: string | undefined) => console.log(`reported ${uuid}`))
+      .catch((error: Error & { code: string }) => console.error(`${error.code}: ${error.message}`));
     // This is also available:
     // rollbar.log(level, message, extraFields);
   }, [rollbar, level, message, extraFields]);
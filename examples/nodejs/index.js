@@ -6,8 +6,10 @@ const rollbar = new Rollbar({
   environment: "prod"
 });
 
-rollbar.log('warning', 'yeehaw', {
-  some: 'stuff'
-});
-
-rollbar.shutdown();
+rollbar
+  .log('warning', 'yeehaw', {
+    some: 'stuff'
+  })
+  .then(uuid => console.log(`reported ${uuid}`))
+  .catch(error => console.error(`${error.code}: ${error.message}`))
+  .finally(() => rollbar.shutdown());
@@ -1,5 +1,3 @@
-const { promisify } = require("util")
-
 const {
     fromConfig,
     log,
@@ -9,23 +7,61 @@ const {
     error,
     critical,
     shutdown,
+    stats,
+    recentErrors,
+    setCheckIgnore,
+    setOnResult,
+    addTransform,
+    setPayload,
+    mergePayload,
 } = require("./index.node")
 
-const logAsync = promisify(log)
-const debugAsync = promisify(debug)
-const infoAsync = promisify(info)
-const warningAsync = promisify(warning)
-const errorAsync = promisify(error)
-const criticalAsync = promisify(critical)
-
 interface Config {
     accessToken: string
     endpoint?: string
     codeVersion?: string
+    ignoredMessages?: string[]
+    payload?: object
+    // Report uncaught exceptions and unhandled rejections at `critical`
+    // level. The process still exits on either unless another listener
+    // handles it.
+    captureUncaught?: boolean
+    captureUnhandledRejections?: boolean
+    // How many delivery errors `recentErrors` keeps. Defaults to 20.
+    maxRecentErrors?: number
 }
 
 type Level = 'debug' | 'info' | 'warning' | 'error' | 'critical'
 
+// `status` and `body` are missing when Rollbar couldn't be reached, and
+// `error` is set whenever the item wasn't accepted.
+interface DeliveryResult {
+    uuid?: string
+    status?: number
+    body?: string
+    error?: Error & { code: string }
+}
+
+interface Failure {
+    timestampMs: number
+    uuid?: string
+    code: string
+    message: string
+    // Set when Rollbar refused the item.
+    status?: number
+    body?: string
+}
+
+interface Stats {
+    sent: number
+    failed: number
+    // Suppressed by `itemsPerMinute` or `maxItems`.
+    dropped: number
+    retried: number
+    queueDepth: number
+    lastError?: Failure
+}
+
 interface ExtraData {
     [key: string]: any
 }
@@ -37,33 +73,68 @@ class Rollbar {
         this.instance = fromConfig(config)
     }
 
-    log(level: Level, message: string, extra?: ExtraData) {
-        return logAsync.apply(this.instance, [level, message, extra].filter(v => !!v))
+    // `Error`s are reported as traces, including their `cause`s.
+    //
+    // Each resolves with the occurrence uuid once Rollbar accepts the item,
+    // or with `undefined` if it was ignored, folded into a duplicate or rate
+    // limited. Rejections are `Error`s with a `code` naming the failure, and
+    // the `status` and `body` of the response if Rollbar refused the item.
+    log(level: Level, message: string | Error, extra?: ExtraData): Promise<string | undefined> {
+        return log.call(this.instance, level, message, extra)
     }
 
-    debug(message: string, extra?: ExtraData) {
-        return debugAsync.apply(this.instance, ['debug', message, extra])
+    debug(message: string | Error, extra?: ExtraData): Promise<string | undefined> {
+        return debug.call(this.instance, message, extra)
     }
 
-    info(message: string, extra?: ExtraData) {
-        return infoAsync.apply(this.instance, ['info', message, extra])
+    info(message: string | Error, extra?: ExtraData): Promise<string | undefined> {
+        return info.call(this.instance, message, extra)
     }
 
-    warning(message: string, extra?: ExtraData) {
-        return warningAsync.apply(this.instance, ['warning', message, extra])
+    warning(message: string | Error, extra?: ExtraData): Promise<string | undefined> {
+        return warning.call(this.instance, message, extra)
     }
 
-    error(message: string, extra?: ExtraData) {
-        return errorAsync.apply(this.instance, ['error', message, extra])
+    error(message: string | Error, extra?: ExtraData): Promise<string | undefined> {
+        return error.call(this.instance, message, extra)
     }
 
-    critical(message: string, extra?: ExtraData) {
-        return criticalAsync.apply(this.instance, ['critical', message, extra])
+    critical(message: string | Error, extra?: ExtraData): Promise<string | undefined> {
+        return critical.call(this.instance, message, extra)
     }
 
     shutdown() {
         return shutdown.call(this.instance)
     }
+
+    stats(): Stats {
+        return stats.call(this.instance)
+    }
+
+    // The most recent delivery errors, oldest first.
+    recentErrors(): Failure[] {
+        return recentErrors.call(this.instance)
+    }
+
+    setCheckIgnore(checkIgnore?: (item: any) => boolean) {
+        return setCheckIgnore.call(this.instance, checkIgnore)
+    }
+
+    setOnResult(onResult?: (result: DeliveryResult) => void) {
+        return setOnResult.call(this.instance, onResult)
+    }
+
+    addTransform(transform: (item: any) => any) {
+        return addTransform.call(this.instance, transform)
+    }
+
+    setPayload(payload: object) {
+        return setPayload.call(this.instance, payload)
+    }
+
+    mergePayload(payload: object) {
+        return mergePayload.call(this.instance, payload)
+    }
 }
 
 module.exports = Rollbar
@@ -1,5 +1,6 @@
 use cbindgen::Config;
 use std::env;
+use std::fs;
 use std::path::PathBuf;
 
 fn main() {
@@ -13,15 +14,160 @@ fn main() {
         .to_string();
 
     let config = Config {
-        namespace: Some(String::from(package_name)),
+        namespace: Some(package_name.clone()),
         language: cbindgen::Language::C,
         cpp_compat: true,
         ..Default::default()
     };
 
-    cbindgen::generate_with_config(&crate_dir, config)
+    // Only the C API and the opaque `Transport` belong in the header, not the
+    // `extern` blocks the wasm build imports from JS.
+    let src = PathBuf::from(&crate_dir).join("src");
+
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(src.join("c.rs"))
+        .with_src(src.join("transport.rs"))
+        .with_src(src.join("types.rs"))
+        .generate()
         .unwrap()
         .write_to_file(&output_file);
+
+    // The C++ wrapper is header-only and includes `rollbar.h` by name, so it
+    // has to sit next to it.
+    fs::copy(
+        PathBuf::from(&crate_dir).join("include/rollbar.hpp"),
+        target_dir().join(format!("{}.hpp", package_name)),
+    )
+    .unwrap();
+
+    // Only C and C++ hosts link the static library the packages point at.
+    if env::var_os("CARGO_FEATURE_C").is_some() {
+        write_packages(&package_name);
+    }
+}
+
+/// Writes `rollbar.pc` and `rollbarConfig.cmake` next to the headers, so C
+/// and C++ projects can link the static library with pkg-config or
+/// `find_package(rollbar)` once `cargo rustc --crate-type staticlib` has
+/// built it. The library depends on system libraries for Rust's std and for
+/// TLS, which a static link has to name explicitly.
+fn write_packages(package_name: &str) {
+    let version = env::var("CARGO_PKG_VERSION").unwrap();
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap();
+
+    let include_dir = target_dir();
+    let lib_dir = profile_dir();
+
+    let library = if target_env == "msvc" {
+        lib_dir.join(format!("{}.lib", package_name))
+    } else {
+        lib_dir.join(format!("lib{}.a", package_name))
+    };
+
+    // What `--print native-static-libs` reports for each platform.
+    let system_libs: &[&str] = match target_os.as_str() {
+        "macos" | "ios" => &[
+            "-framework Security",
+            "-framework CoreFoundation",
+            "-framework SystemConfiguration",
+            "-liconv",
+            "-lSystem",
+            "-lc",
+            "-lm",
+        ],
+        "windows" => &[
+            "-lws2_32",
+            "-lbcrypt",
+            "-ladvapi32",
+            "-lcrypt32",
+            "-lsecur32",
+            "-lncrypt",
+            "-luserenv",
+            "-lntdll",
+            "-lkernel32",
+        ],
+        _ => &[
+            "-lssl",
+            "-lcrypto",
+            "-lgcc_s",
+            "-lutil",
+            "-lrt",
+            "-lpthread",
+            "-lm",
+            "-ldl",
+            "-lc",
+        ],
+    };
+
+    let pkg_config = format!(
+        "includedir={include_dir}\n\
+         \n\
+         Name: {name}\n\
+         Description: Rollbar notifier for C and C++\n\
+         Version: {version}\n\
+         Cflags: -I${{includedir}}\n\
+         Libs: {library} {system_libs}\n",
+        include_dir = include_dir.display(),
+        name = package_name,
+        version = version,
+        library = library.display(),
+        system_libs = system_libs.join(" "),
+    );
+
+    fs::write(include_dir.join(format!("{}.pc", package_name)), pkg_config).unwrap();
+
+    let cmake_config = format!(
+        "# Generated by the {name} build script.\n\
+         \n\
+         if(NOT TARGET {name}::{name})\n\
+         \x20   add_library({name}::{name} STATIC IMPORTED)\n\
+         \x20   set_target_properties({name}::{name} PROPERTIES\n\
+         \x20       IMPORTED_LOCATION \"{library}\"\n\
+         \x20       INTERFACE_INCLUDE_DIRECTORIES \"{include_dir}\"\n\
+         \x20       INTERFACE_LINK_LIBRARIES \"{system_libs}\")\n\
+         endif()\n",
+        name = package_name,
+        library = library.display(),
+        include_dir = include_dir.display(),
+        system_libs = system_libs.join(";"),
+    );
+
+    fs::write(
+        include_dir.join(format!("{}Config.cmake", package_name)),
+        cmake_config,
+    )
+    .unwrap();
+
+    // Any version is accepted until the API is stable.
+    let cmake_version = format!(
+        "set(PACKAGE_VERSION \"{version}\")\n\
+         set(PACKAGE_VERSION_COMPATIBLE TRUE)\n\
+         if(PACKAGE_FIND_VERSION STREQUAL PACKAGE_VERSION)\n\
+         \x20   set(PACKAGE_VERSION_EXACT TRUE)\n\
+         endif()\n",
+        version = version,
+    );
+
+    fs::write(
+        include_dir.join(format!("{}ConfigVersion.cmake", package_name)),
+        cmake_version,
+    )
+    .unwrap();
+}
+
+/// The directory cargo puts the built library in, e.g. `target/debug` or
+/// `target/x86_64-unknown-linux-gnu/release` when cross compiling.
+fn profile_dir() -> PathBuf {
+    let target = env::var("TARGET").unwrap();
+    let profile = env::var("PROFILE").unwrap();
+
+    if target == env::var("HOST").unwrap() {
+        target_dir().join(profile)
+    } else {
+        target_dir().join(target).join(profile)
+    }
 }
 
 /// Find the location of the `target/` directory. Note that this may be
@@ -0,0 +1,145 @@
+// RAII wrapper around the C API in rollbar.h. Copied next to rollbar.h by
+// the build script.
+
+#pragma once
+
+#include <stdexcept>
+#include <string>
+#include <utility>
+#include <vector>
+
+#include "rollbar.h"
+
+namespace rollbar {
+
+// Thrown when a call fails. `code()` is one of the `ROLLBAR_ERR_*` codes and
+// `what()` the message from `rollbar_last_error`.
+class Exception : public std::runtime_error {
+public:
+    Exception(int code, const std::string& message)
+        : std::runtime_error(message), code_(code) {}
+
+    int code() const noexcept { return code_; }
+
+private:
+    int code_;
+};
+
+namespace detail {
+
+inline std::string last_error() {
+    int len = rollbar_last_error(nullptr, 0);
+
+    std::vector<char> message(len + 1);
+    rollbar_last_error(message.data(), message.size());
+
+    return std::string(message.data(), len);
+}
+
+inline void check(int code) {
+    if (code != ROLLBAR_OK) {
+        throw Exception(code, last_error());
+    }
+}
+
+}  // namespace detail
+
+// Owns a transport. The destructor sends queued items and frees it; call
+// `shutdown()` first to find out whether that worked. Move-only.
+class Client {
+public:
+    explicit Client(const ConfigCompat& config) {
+        detail::check(rollbar_create_transport(config, &transport_));
+    }
+
+    explicit Client(const std::string& access_token) : Client(config(access_token)) {}
+
+    Client(const Client&) = delete;
+    Client& operator=(const Client&) = delete;
+
+    Client(Client&& other) noexcept
+        : transport_(std::exchange(other.transport_, nullptr)) {}
+
+    Client& operator=(Client&& other) noexcept {
+        if (this != &other) {
+            reset();
+            transport_ = std::exchange(other.transport_, nullptr);
+        }
+
+        return *this;
+    }
+
+    ~Client() { reset(); }
+
+    // `extra` is a JSON object of extra data, or empty for none.
+    void log(LevelCompat level, const std::string& message, const std::string& extra = "") {
+        detail::check(rollbar_log_with_extra(
+            transport(),
+            level,
+            message.c_str(),
+            extra.empty() ? nullptr : extra.c_str()));
+    }
+
+    void debug(const std::string& message, const std::string& extra = "") {
+        log(LevelCompat::Debug, message, extra);
+    }
+
+    void info(const std::string& message, const std::string& extra = "") {
+        log(LevelCompat::Info, message, extra);
+    }
+
+    void warning(const std::string& message, const std::string& extra = "") {
+        log(LevelCompat::Warning, message, extra);
+    }
+
+    void error(const std::string& message, const std::string& extra = "") {
+        log(LevelCompat::Error, message, extra);
+    }
+
+    void critical(const std::string& message, const std::string& extra = "") {
+        log(LevelCompat::Critical, message, extra);
+    }
+
+    // Sends every queued item, throwing if any failed.
+    void shutdown() { detail::check(rollbar_shutdown(transport())); }
+
+    // Counts of sent, failed, dropped and retried items so far.
+    StatsCompat stats() const {
+        StatsCompat stats = {};
+        detail::check(rollbar_stats(transport(), &stats));
+
+        return stats;
+    }
+
+    // The underlying transport, for the rest of the C API. Null once moved
+    // from.
+    Transport* get() const noexcept { return transport_; }
+
+private:
+    static ConfigCompat config(const std::string& access_token) {
+        ConfigCompat config = {};
+        config.access_token = access_token.c_str();
+
+        return config;
+    }
+
+    Transport* transport() const {
+        if (transport_ == nullptr) {
+            throw Exception(ROLLBAR_ERR_ARGUMENT, "client was moved from");
+        }
+
+        return transport_;
+    }
+
+    void reset() noexcept {
+        if (transport_ != nullptr) {
+            rollbar_shutdown(transport_);
+            rollbar_destroy_transport(transport_);
+            transport_ = nullptr;
+        }
+    }
+
+    Transport* transport_ = nullptr;
+};
+
+}  // namespace rollbar
@@ -1,18 +1,156 @@
+// These functions are only meant to be called from C, which passes raw
+// pointers for everything.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+#[cfg(target_os = "linux")]
+use crate::crash;
+
 use crate::{
-    types::{Item, Level},
+    native,
+    types::{Exception, Frame, Item, Level, Trace},
     Config, Transport,
 };
 
 use ::{
-    libc::{c_char, c_int},
-    once_cell::sync::OnceCell,
-    std::{collections::HashMap, ffi::CStr},
+    libc::{c_char, c_int, c_void},
+    serde_json::Value,
+    std::{
+        cell::RefCell,
+        collections::HashMap,
+        ffi::{CStr, CString},
+        fmt::Display,
+        panic::{self, AssertUnwindSafe},
+        path::Path,
+        ptr, slice,
+    },
 };
 
+/// The call succeeded.
+pub const ROLLBAR_OK: c_int = 0;
+/// An argument was missing or invalid, e.g. malformed JSON.
+pub const ROLLBAR_ERR_ARGUMENT: c_int = 1;
+/// The transport reported an error.
+pub const ROLLBAR_ERR_TRANSPORT: c_int = 2;
+/// The library panicked. The transport may be unusable.
+pub const ROLLBAR_ERR_PANIC: c_int = 3;
+/// The call isn't supported on this platform.
+pub const ROLLBAR_ERR_UNSUPPORTED: c_int = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records `error` for `rollbar_last_error` and returns `code`.
+fn fail(code: c_int, error: impl Display) -> c_int {
+    let message = CString::new(error.to_string().replace('\0', ""))
+        .unwrap_or_else(|_| CString::new("unknown error").unwrap());
+
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+
+    code
+}
+
+fn result<T>(result: Result<T, crate::transport::Error>) -> c_int {
+    match result {
+        Ok(_) => ROLLBAR_OK,
+        Err(error) => fail(ROLLBAR_ERR_TRANSPORT, error),
+    }
+}
+
+/// Runs the body of an `extern "C"` function, turning a panic into
+/// `ROLLBAR_ERR_PANIC` so it never unwinds into C.
+fn guard(body: impl FnOnce() -> c_int) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(code) => code,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+
+            fail(ROLLBAR_ERR_PANIC, format!("panicked: {}", message))
+        }
+    }
+}
+
+fn transport<'a>(transport: *mut Transport) -> Result<&'a Transport, c_int> {
+    match unsafe { transport.as_ref() } {
+        Some(transport) => Ok(transport),
+        None => Err(fail(ROLLBAR_ERR_ARGUMENT, "transport is null")),
+    }
+}
+
+/// Copies the message of the last error on this thread into `buf`, truncated
+/// to `len - 1` bytes and NUL terminated. Returns the full length of the
+/// message, not counting the terminator, so a return value of `len` or more
+/// means it was truncated. Returns 0 if no call on this thread has failed.
+/// Errors are not cleared by later successful calls. `buf` may be null to
+/// query the length.
+#[no_mangle]
+pub extern "C" fn rollbar_last_error(buf: *mut c_char, len: usize) -> c_int {
+    guard(|| {
+        LAST_ERROR.with(|last_error| {
+            let last_error = last_error.borrow();
+
+            let message = match &*last_error {
+                Some(message) => message.as_bytes(),
+                None => return 0,
+            };
+
+            copy_to(message, buf, len);
+
+            message.len().min(c_int::MAX as usize) as c_int
+        })
+    })
+}
+
+/// Copies `bytes` into `buf`, truncated to `len - 1` bytes and NUL
+/// terminated. Does nothing if `buf` is null or `len` is 0.
+fn copy_to(bytes: &[u8], buf: *mut c_char, len: usize) {
+    if !buf.is_null() && len > 0 {
+        let copied = bytes.len().min(len - 1);
+
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, buf, copied);
+            *buf.add(copied) = 0;
+        }
+    }
+}
+
+/// Settings for `rollbar_create_transport`. Zero-initialize it and set what
+/// you need: null strings and zero limits mean "use the default". Settings
+/// that only apply to the JS bindings, such as capturing uncaught exceptions
+/// or browser telemetry, are not part of the C API.
 #[repr(C)]
 pub struct ConfigCompat {
+    /// Defaults to `https://api.rollbar.com`.
     uri: *const c_char,
+    /// Required.
     access_token: *const c_char,
+    code_version: *const c_char,
+    environment: *const c_char,
+    /// Maximum number of items sent per minute. 0 is unlimited.
+    items_per_minute: u32,
+    /// Maximum number of items sent for the life of the process. 0 is
+    /// unlimited.
+    max_items: u32,
+    /// Repeats of an item seen within this many milliseconds of the first
+    /// occurrence are counted instead of sent. 0 disables it.
+    dedup_window_ms: u64,
+    /// `ignored_messages_len` regular expressions. Items whose message
+    /// matches any of them are not sent. Null entries are skipped.
+    ignored_messages: *const *const c_char,
+    ignored_messages_len: usize,
+    /// A JSON object deep-merged into the `data` of every item.
+    payload: *const c_char,
+    /// The directory given to `rollbar_install_crash_handler`. Crash records
+    /// left there by an earlier process are sent as critical items. Linux
+    /// only.
+    crash_spool_dir: *const c_char,
+    /// How many delivery errors `rollbar_recent_errors` keeps. 0 keeps the
+    /// default of 20.
+    max_recent_errors: usize,
 }
 
 #[repr(C)]
@@ -38,70 +176,1159 @@ impl From<LevelCompat> for Level {
     }
 }
 
+/// Reads a C string, replacing invalid UTF-8 with U+FFFD. Null is `None`.
+fn optional_string(string: *const c_char) -> Option<String> {
+    if string.is_null() {
+        return None;
+    }
+
+    Some(
+        unsafe { CStr::from_ptr(string) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+fn optional_json<T: serde::de::DeserializeOwned>(json: *const c_char) -> Result<Option<T>, c_int> {
+    match optional_string(json) {
+        Some(json) => match serde_json::from_str(&json) {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => Err(fail(ROLLBAR_ERR_ARGUMENT, error)),
+        },
+        None => Ok(None),
+    }
+}
+
+impl ConfigCompat {
+    fn to_config(&self) -> Result<Config, c_int> {
+        let access_token = match optional_string(self.access_token) {
+            Some(access_token) => access_token,
+            None => return Err(fail(ROLLBAR_ERR_ARGUMENT, "access_token is required")),
+        };
+
+        let ignored_messages = if self.ignored_messages.is_null() {
+            vec![]
+        } else {
+            let patterns =
+                unsafe { slice::from_raw_parts(self.ignored_messages, self.ignored_messages_len) };
+
+            patterns
+                .iter()
+                .filter_map(|pattern| optional_string(*pattern))
+                .collect()
+        };
+
+        let payload: Value = optional_json(self.payload)?.unwrap_or_default();
+
+        Ok(Config::builder()
+            .access_token(access_token)
+            .uri(optional_string(self.uri).unwrap_or_else(Config::default_uri))
+            .code_version(optional_string(self.code_version))
+            .environment(optional_string(self.environment))
+            .items_per_minute(Some(self.items_per_minute))
+            .max_items(Some(self.max_items))
+            .dedup_window_ms(Some(self.dedup_window_ms))
+            .ignored_messages(ignored_messages)
+            .payload(payload)
+            .max_recent_errors(match self.max_recent_errors {
+                0 => Config::default_max_recent_errors(),
+                max_recent_errors => max_recent_errors,
+            })
+            .build())
+    }
+}
+
+/// Creates a transport and stores it in `out_transport`. Returns
+/// `ROLLBAR_OK`, `ROLLBAR_ERR_ARGUMENT` for an invalid config or a null
+/// `out_transport`, or `ROLLBAR_ERR_TRANSPORT`. Free the transport with
+/// `rollbar_destroy_transport`.
 #[no_mangle]
-pub extern "C" fn create_transport(
+pub extern "C" fn rollbar_create_transport(
     in_config: ConfigCompat,
-    out_transport: &mut *mut Transport,
+    out_transport: *mut *mut Transport,
 ) -> c_int {
-    let access_token = match unsafe { CStr::from_ptr(in_config.access_token) }.to_str() {
-        Ok(access_token) => access_token.to_owned(),
-        Err(error) => {
-            eprintln!("access_token required: {}", error);
-            return 0;
+    guard(|| {
+        if out_transport.is_null() {
+            return fail(ROLLBAR_ERR_ARGUMENT, "out_transport is null");
         }
-    };
 
-    let uri = match unsafe { CStr::from_ptr(in_config.uri) }.to_str() {
-        Ok(uri) => uri.to_owned(),
-        Err(_) => Config::default_uri(),
-    };
+        let config = match in_config.to_config() {
+            Ok(config) => config,
+            Err(code) => return code,
+        };
 
-    let config = Config { access_token, uri };
+        let transport = match Transport::new(config) {
+            Ok(transport) => transport,
+            Err(error) => return fail(ROLLBAR_ERR_TRANSPORT, error),
+        };
 
-    match Transport::new(config) {
-        Ok(transport) => {
-            *out_transport = Box::into_raw(Box::new(transport));
-            0
-        }
-        Err(error) => {
-            eprintln!("error creating transport: {}", error);
-            1
+        // A spool that can't be read shouldn't keep the host from reporting.
+        #[cfg(target_os = "linux")]
+        if let Some(spool_dir) = optional_string(in_config.crash_spool_dir) {
+            let _ = crash::report(&transport, Path::new(&spool_dir));
         }
-    }
+
+        unsafe { *out_transport = Box::into_raw(Box::new(transport)) };
+
+        ROLLBAR_OK
+    })
+}
+
+/// Queues `message` at `level`. Equivalent to `rollbar_log_with_extra`
+/// without extra data.
+#[no_mangle]
+pub extern "C" fn rollbar_log(
+    transport: *mut Transport,
+    level: LevelCompat,
+    message: *const c_char,
+) -> c_int {
+    rollbar_log_with_extra(transport, level, message, ptr::null())
 }
 
+/// Queues `message` at `level`, with `extra` as a JSON object of extra data.
+/// A null message is sent as an empty one and a null `extra` adds nothing.
+/// Returns `ROLLBAR_OK`, `ROLLBAR_ERR_ARGUMENT` for a null transport or
+/// invalid extra data, or `ROLLBAR_ERR_TRANSPORT`.
 #[no_mangle]
-pub extern "C" fn log(
-    transport: &mut Transport,
+pub extern "C" fn rollbar_log_with_extra(
+    transport: *mut Transport,
     level: LevelCompat,
     message: *const c_char,
+    extra: *const c_char,
 ) -> c_int {
-    let message = match unsafe { CStr::from_ptr(message) }.to_str() {
-        Ok(message) => message,
-        Err(error) => {
-            eprintln!("error casting string: {}", error);
-            return 1;
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        let extra: HashMap<String, Value> = match optional_json(extra) {
+            Ok(extra) => extra.unwrap_or_default(),
+            Err(code) => return code,
+        };
+
+        let message = optional_string(message).unwrap_or_default();
+        let item = Item::from((Level::from(level), message, extra));
+
+        result(transport.send(item))
+    })
+}
+
+/// Sends every queued item and stops the transport. Returns
+/// `ROLLBAR_ERR_TRANSPORT` if any item failed to send. The transport must
+/// still be freed with `rollbar_destroy_transport`.
+#[no_mangle]
+pub extern "C" fn rollbar_shutdown(transport: *mut Transport) -> c_int {
+    guard(|| match self::transport(transport) {
+        Ok(transport) => result(transport.shutdown()),
+        Err(code) => code,
+    })
+}
+
+/// Frees a transport created by `rollbar_create_transport`. Call
+/// `rollbar_shutdown` first to send queued items. Passing null does nothing.
+#[no_mangle]
+pub extern "C" fn rollbar_destroy_transport(transport: *mut Transport) {
+    guard(|| {
+        if !transport.is_null() {
+            drop(unsafe { Box::from_raw(transport) });
         }
-    };
 
-    let item = Item::from((Level::from(level), message, HashMap::new()));
+        ROLLBAR_OK
+    });
+}
 
-    match transport.send(item) {
-        Err(error) => {
-            eprintln!("error sending item: {}", error);
-            1
+/// Counts of what became of items, filled in by `rollbar_stats`.
+#[repr(C)]
+pub struct StatsCompat {
+    /// Items Rollbar accepted.
+    sent: u64,
+    /// Items that could not be delivered.
+    failed: u64,
+    /// Items suppressed by `items_per_minute` or `max_items`.
+    dropped: u64,
+    retried: u64,
+    /// Items queued but not yet handled.
+    queue_depth: u64,
+    /// The HTTP status of the latest error if Rollbar refused the item, 0
+    /// otherwise.
+    last_error_status: u16,
+}
+
+/// Stores the transport's counts in `out_stats`. Returns `ROLLBAR_OK`,
+/// `ROLLBAR_ERR_ARGUMENT` for a null transport or `out_stats`, or
+/// `ROLLBAR_ERR_TRANSPORT`.
+#[no_mangle]
+pub extern "C" fn rollbar_stats(transport: *mut Transport, out_stats: *mut StatsCompat) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        if out_stats.is_null() {
+            return fail(ROLLBAR_ERR_ARGUMENT, "out_stats is null");
         }
-        _ => 0,
+
+        let stats = match transport.stats() {
+            Ok(stats) => stats,
+            Err(error) => return fail(ROLLBAR_ERR_TRANSPORT, error),
+        };
+
+        let last_error_status = stats
+            .last_error
+            .and_then(|failure| failure.status)
+            .unwrap_or(0);
+
+        unsafe {
+            *out_stats = StatsCompat {
+                sent: stats.sent,
+                failed: stats.failed,
+                dropped: stats.dropped,
+                retried: stats.retried,
+                queue_depth: stats.queue_depth,
+                last_error_status,
+            }
+        };
+
+        ROLLBAR_OK
+    })
+}
+
+/// Copies the most recent delivery errors into `buf` as a JSON array, oldest
+/// first, truncated and NUL terminated like `rollbar_last_error`. Each has a
+/// `timestampMs`, `uuid`, `code`, `message`, and the `status` and `body` of
+/// the response if Rollbar refused the item. The full length is stored in
+/// `out_len`, which may be null.
+#[no_mangle]
+pub extern "C" fn rollbar_recent_errors(
+    transport: *mut Transport,
+    buf: *mut c_char,
+    len: usize,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        let errors = match transport
+            .recent_errors()
+            .map(|errors| serde_json::to_string(&errors))
+        {
+            Ok(Ok(errors)) => errors,
+            Ok(Err(error)) => return fail(ROLLBAR_ERR_TRANSPORT, error),
+            Err(error) => return fail(ROLLBAR_ERR_TRANSPORT, error),
+        };
+
+        copy_to(errors.as_bytes(), buf, len);
+
+        if let Some(out_len) = unsafe { out_len.as_mut() } {
+            *out_len = errors.len();
+        }
+
+        ROLLBAR_OK
+    })
+}
+
+/// Creates a trace for an exception of `class`, `Error` if null, with an
+/// optional `message`. Add frames to it, oldest first, then pass it to
+/// `rollbar_send_trace` or `rollbar_trace_free`. Returns null only if the
+/// library panicked.
+#[no_mangle]
+pub extern "C" fn rollbar_trace_new(class: *const c_char, message: *const c_char) -> *mut Trace {
+    let mut trace = ptr::null_mut();
+
+    guard(|| {
+        let class = optional_string(class).unwrap_or_else(|| "Error".into());
+        let exception = Exception::new(class, optional_string(message));
+
+        trace = Box::into_raw(Box::new(Trace::new(vec![], exception)));
+
+        ROLLBAR_OK
+    });
+
+    trace
+}
+
+fn trace<'a>(trace: *mut Trace) -> Result<&'a mut Trace, c_int> {
+    match unsafe { trace.as_mut() } {
+        Some(trace) => Ok(trace),
+        None => Err(fail(ROLLBAR_ERR_ARGUMENT, "trace is null")),
     }
 }
 
+/// Appends a frame to `trace` as its newest. A `line` or `col` of 0 means
+/// unknown, a null `filename` is reported as `<unknown>` and a null `method`
+/// is left out.
 #[no_mangle]
-pub extern "C" fn shutdown(transport: &mut Transport) -> c_int {
-    match transport.shutdown() {
-        Err(error) => {
-            eprintln!("error sending item: {}", error);
-            1
+pub extern "C" fn rollbar_trace_add_frame(
+    trace: *mut Trace,
+    filename: *const c_char,
+    line: u32,
+    col: u32,
+    method: *const c_char,
+) -> c_int {
+    guard(|| {
+        let trace = match self::trace(trace) {
+            Ok(trace) => trace,
+            Err(code) => return code,
+        };
+
+        let filename = optional_string(filename).unwrap_or_else(|| "<unknown>".into());
+        let position = |n| if n == 0 { None } else { Some(n) };
+
+        trace.push(Frame::new(
+            filename,
+            position(line),
+            position(col),
+            optional_string(method),
+        ));
+
+        ROLLBAR_OK
+    })
+}
+
+/// Appends the calling thread's stack to `trace`, symbolicated from the
+/// library's debug info, leaving out `skip` of the innermost frames besides
+/// the caller's. Symbols are only as good as the debug info shipped with the
+/// binary.
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn rollbar_trace_capture(trace: *mut Trace, skip: usize) -> c_int {
+    guard(|| {
+        let trace = match self::trace(trace) {
+            Ok(trace) => trace,
+            Err(code) => return code,
+        };
+
+        for frame in native::capture(rollbar_trace_capture as *mut c_void, skip) {
+            trace.push(frame);
+        }
+
+        ROLLBAR_OK
+    })
+}
+
+/// Frees a trace without sending it. Passing null does nothing.
+#[no_mangle]
+pub extern "C" fn rollbar_trace_free(trace: *mut Trace) {
+    guard(|| {
+        if !trace.is_null() {
+            drop(unsafe { Box::from_raw(trace) });
         }
-        _ => 0,
+
+        ROLLBAR_OK
+    });
+}
+
+/// Queues `trace` at `level` and frees it, whether or not it could be
+/// queued. Returns `ROLLBAR_OK`, `ROLLBAR_ERR_ARGUMENT` for a null
+/// transport or trace, or `ROLLBAR_ERR_TRANSPORT`.
+#[no_mangle]
+pub extern "C" fn rollbar_send_trace(
+    transport: *mut Transport,
+    level: LevelCompat,
+    trace: *mut Trace,
+) -> c_int {
+    guard(|| {
+        if trace.is_null() {
+            return fail(ROLLBAR_ERR_ARGUMENT, "trace is null");
+        }
+
+        let trace = unsafe { Box::from_raw(trace) };
+
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        let item = Item::from_traces(Level::from(level), vec![*trace], HashMap::new());
+
+        result(transport.send(item))
+    })
+}
+
+/// Installs handlers for `SIGSEGV`, `SIGABRT`, `SIGBUS` and `SIGFPE` that
+/// write a crash record to `spool_dir` and re-raise the signal. Records
+/// already in `spool_dir` are sent through `transport` now; pass the same
+/// directory as `crash_spool_dir` to have `rollbar_create_transport` send
+/// them in the next process. Only one handler can be installed, and stack
+/// overflows are only caught on the calling thread. Returns
+/// `ROLLBAR_ERR_UNSUPPORTED` on platforms other than Linux.
+#[no_mangle]
+pub extern "C" fn rollbar_install_crash_handler(
+    transport: *mut Transport,
+    spool_dir: *const c_char,
+) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        let spool_dir = match optional_string(spool_dir) {
+            Some(spool_dir) => spool_dir,
+            None => return fail(ROLLBAR_ERR_ARGUMENT, "spool_dir is required"),
+        };
+
+        install_crash_handler(transport, Path::new(&spool_dir))
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn install_crash_handler(transport: &Transport, spool_dir: &Path) -> c_int {
+    if let Err(error) = crash::install(spool_dir) {
+        return fail(ROLLBAR_ERR_ARGUMENT, error);
+    }
+
+    match crash::report(transport, spool_dir) {
+        Ok(_) => ROLLBAR_OK,
+        Err(error) => fail(ROLLBAR_ERR_ARGUMENT, error),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn install_crash_handler(_: &Transport, _: &Path) -> c_int {
+    fail(
+        ROLLBAR_ERR_UNSUPPORTED,
+        "crash handlers are only supported on Linux",
+    )
+}
+
+/// Receives the prepared item serialized as JSON along with the `user_data`
+/// it was registered with. Returning `true` keeps the item from being sent.
+pub type CheckIgnoreCallback =
+    Option<extern "C" fn(item: *const c_char, user_data: *mut c_void) -> bool>;
+
+struct UserData(*mut c_void);
+
+// The caller is responsible for `user_data` being usable from the transport's
+// threads for as long as the callback is registered.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    fn as_ptr(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Registers `callback` as the transport's ignore predicate. Passing a null
+/// callback removes it.
+#[no_mangle]
+pub extern "C" fn rollbar_set_check_ignore(
+    transport: *mut Transport,
+    callback: CheckIgnoreCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return result(transport.clear_check_ignore()),
+        };
+
+        let user_data = UserData(user_data);
+
+        let registered = transport.set_check_ignore(move |item| {
+            let item = match serde_json::to_string(item).map(CString::new) {
+                Ok(Ok(item)) => item,
+                _ => return false,
+            };
+
+            callback(item.as_ptr(), user_data.as_ptr())
+        });
+
+        result(registered)
+    })
+}
+
+/// Receives the prepared item serialized as JSON along with the `user_data`
+/// it was registered with. To replace the item, return a JSON string
+/// allocated with `malloc`; the library frees it. Returning null leaves the
+/// item unchanged.
+pub type TransformCallback =
+    Option<extern "C" fn(item: *const c_char, user_data: *mut c_void) -> *mut c_char>;
+
+/// Appends `callback` to the transforms run on every item before it is sent.
+/// Passing a null callback does nothing.
+#[no_mangle]
+pub extern "C" fn rollbar_add_transform(
+    transport: *mut Transport,
+    callback: TransformCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return ROLLBAR_OK,
+        };
+
+        let user_data = UserData(user_data);
+
+        let registered = transport.add_transform(move |item| {
+            let json = match serde_json::to_string(item).map(CString::new) {
+                Ok(Ok(json)) => json,
+                _ => return,
+            };
+
+            let replacement = callback(json.as_ptr(), user_data.as_ptr());
+
+            if replacement.is_null() {
+                return;
+            }
+
+            let parsed = optional_json(replacement);
+
+            unsafe { libc::free(replacement as *mut c_void) };
+
+            if let Ok(Some(replacement)) = parsed {
+                *item = replacement;
+            }
+        });
+
+        result(registered)
+    })
+}
+
+/// Receives the outcome of every item the transport tries to send, along
+/// with the `user_data` it was registered with: the item's uuid, the HTTP
+/// status, the response body and the error message. `status` is 0 if no
+/// response was received, and the strings are null when missing. They are
+/// only valid during the call, which happens on the transport's worker
+/// thread.
+pub type OnResultCallback = Option<
+    extern "C" fn(
+        uuid: *const c_char,
+        status: u16,
+        body: *const c_char,
+        error: *const c_char,
+        user_data: *mut c_void,
+    ),
+>;
+
+/// Registers `callback` to observe delivery results. Passing a null callback
+/// removes it.
+#[no_mangle]
+pub extern "C" fn rollbar_set_on_result(
+    transport: *mut Transport,
+    callback: OnResultCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return result(transport.clear_on_result()),
+        };
+
+        let user_data = UserData(user_data);
+
+        let registered = transport.set_on_result(move |delivery| {
+            let string = |value: &str| CString::new(value.replace('\0', "")).ok();
+
+            let uuid = delivery.uuid.and_then(string);
+            let body = delivery.body.and_then(string);
+            let error = delivery.error.and_then(|error| string(&error.to_string()));
+
+            let as_ptr =
+                |value: &Option<CString>| value.as_deref().map_or(ptr::null(), CStr::as_ptr);
+
+            callback(
+                as_ptr(&uuid),
+                delivery.status.unwrap_or(0),
+                as_ptr(&body),
+                as_ptr(&error),
+                user_data.as_ptr(),
+            )
+        });
+
+        result(registered)
+    })
+}
+
+/// Replaces the defaults deep-merged into every item with the JSON object in
+/// `payload`. A null `payload` clears them.
+#[no_mangle]
+pub extern "C" fn rollbar_set_payload(transport: *mut Transport, payload: *const c_char) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        match optional_json(payload) {
+            Ok(payload) => result(transport.set_payload(payload.unwrap_or_default())),
+            Err(code) => code,
+        }
+    })
+}
+
+/// Deep-merges the JSON object in `payload` into the defaults. A null
+/// `payload` does nothing.
+#[no_mangle]
+pub extern "C" fn rollbar_merge_payload(
+    transport: *mut Transport,
+    payload: *const c_char,
+) -> c_int {
+    guard(|| {
+        let transport = match self::transport(transport) {
+            Ok(transport) => transport,
+            Err(code) => return code,
+        };
+
+        match optional_json(payload) {
+            Ok(Some(payload)) => result(transport.merge_payload(payload)),
+            Ok(None) => ROLLBAR_OK,
+            Err(code) => code,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::runtime;
+
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn last_error() -> String {
+        let mut buf = [0 as c_char; 256];
+        rollbar_last_error(buf.as_mut_ptr(), buf.len());
+
+        unsafe { CStr::from_ptr(buf.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn string(string: &str) -> CString {
+        CString::new(string).unwrap()
+    }
+
+    fn config(access_token: &CStr) -> ConfigCompat {
+        ConfigCompat {
+            uri: ptr::null(),
+            access_token: access_token.as_ptr(),
+            code_version: ptr::null(),
+            environment: ptr::null(),
+            items_per_minute: 0,
+            max_items: 0,
+            dedup_window_ms: 0,
+            ignored_messages: ptr::null(),
+            ignored_messages_len: 0,
+            payload: ptr::null(),
+            crash_spool_dir: ptr::null(),
+            max_recent_errors: 0,
+        }
+    }
+
+    fn mock_server() -> MockServer {
+        runtime::block_on(async {
+            let mock_server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .and(path("api/1/item"))
+                .respond_with(ResponseTemplate::new(200))
+                .mount(&mock_server)
+                .await;
+
+            mock_server
+        })
+        .unwrap()
+    }
+
+    fn received(mock_server: &MockServer) -> Vec<Value> {
+        runtime::block_on(mock_server.received_requests())
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|request| request.body_json().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn missing_access_token_is_an_argument_error() {
+        let token = string("token");
+        let mut config = config(&token);
+        config.access_token = ptr::null();
+
+        let mut transport = ptr::null_mut();
+
+        assert_eq!(
+            rollbar_create_transport(config, &mut transport),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert!(transport.is_null());
+        assert_eq!(last_error(), "access_token is required");
+    }
+
+    #[test]
+    fn config_fields_are_converted() {
+        let (token, environment) = (string("token"), string("staging"));
+        let (pattern, payload) = (string("^ignored"), string(r#"{"person": {"id": 1}}"#));
+        let patterns = [pattern.as_ptr(), ptr::null()];
+
+        let mut config = config(&token);
+        config.environment = environment.as_ptr();
+        config.items_per_minute = 10;
+        config.ignored_messages = patterns.as_ptr();
+        config.ignored_messages_len = patterns.len();
+        config.payload = payload.as_ptr();
+
+        let config = config.to_config().unwrap();
+
+        assert_eq!(config.uri, Config::default_uri());
+        assert_eq!(config.environment.as_deref(), Some("staging"));
+        assert_eq!(config.code_version, None);
+        assert_eq!(config.items_per_minute, Some(10));
+        assert_eq!(config.ignored_messages, vec!["^ignored".to_owned()]);
+        assert_eq!(config.payload, serde_json::json!({ "person": { "id": 1 } }));
+        assert_eq!(
+            config.max_recent_errors,
+            Config::default_max_recent_errors()
+        );
+    }
+
+    #[test]
+    fn max_recent_errors_is_converted() {
+        let token = string("token");
+
+        let mut config = config(&token);
+        config.max_recent_errors = 5;
+
+        assert_eq!(config.to_config().unwrap().max_recent_errors, 5);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let token = CString::new(vec![b'a', 0xff, b'b']).unwrap();
+
+        let config = config(&token).to_config().unwrap();
+
+        assert_eq!(config.access_token, "a\u{fffd}b");
+    }
+
+    #[test]
+    fn last_error_is_truncated_to_the_buffer() {
+        fail(ROLLBAR_ERR_ARGUMENT, "0123456789");
+
+        let mut buf = [1 as c_char; 4];
+        let len = rollbar_last_error(buf.as_mut_ptr(), buf.len());
+
+        assert_eq!(len, 10);
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str(), Ok("012"));
+
+        assert_eq!(rollbar_last_error(ptr::null_mut(), 0), 10);
+    }
+
+    #[test]
+    fn null_transport_is_an_argument_error() {
+        let transport = ptr::null_mut();
+        let payload = string("{}");
+
+        assert_eq!(
+            rollbar_log(transport, LevelCompat::Info, ptr::null()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert_eq!(last_error(), "transport is null");
+
+        assert_eq!(rollbar_shutdown(transport), ROLLBAR_ERR_ARGUMENT);
+        assert_eq!(
+            rollbar_set_check_ignore(transport, None, ptr::null_mut()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert_eq!(
+            rollbar_add_transform(transport, None, ptr::null_mut()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert_eq!(
+            rollbar_set_payload(transport, payload.as_ptr()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert_eq!(
+            rollbar_merge_payload(transport, payload.as_ptr()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+
+        rollbar_destroy_transport(transport);
+
+        let token = string("token");
+        assert_eq!(
+            rollbar_create_transport(config(&token), ptr::null_mut()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+    }
+
+    #[test]
+    fn panics_are_caught() {
+        let code = guard(|| panic!("boom"));
+
+        assert_eq!(code, ROLLBAR_ERR_PANIC);
+        assert_eq!(last_error(), "panicked: boom");
+    }
+
+    #[test]
+    fn items_are_sent_through_the_c_api() {
+        let mock_server = mock_server();
+
+        let (token, uri) = (string("token"), string(&mock_server.uri()));
+        let payload = string(r#"{"environment": "test"}"#);
+
+        let mut config = config(&token);
+        config.uri = uri.as_ptr();
+        config.payload = payload.as_ptr();
+
+        let mut transport = ptr::null_mut();
+        assert_eq!(rollbar_create_transport(config, &mut transport), ROLLBAR_OK);
+
+        let (message, extra) = (string("hello"), string(r#"{"answer": 42}"#));
+        let invalid = CString::new(vec![b'h', 0xff]).unwrap();
+
+        assert_eq!(
+            rollbar_log_with_extra(
+                transport,
+                LevelCompat::Info,
+                message.as_ptr(),
+                extra.as_ptr()
+            ),
+            ROLLBAR_OK
+        );
+        assert_eq!(
+            rollbar_log(transport, LevelCompat::Warning, invalid.as_ptr()),
+            ROLLBAR_OK
+        );
+        assert_eq!(
+            rollbar_log(transport, LevelCompat::Debug, ptr::null()),
+            ROLLBAR_OK
+        );
+        assert_eq!(rollbar_merge_payload(transport, ptr::null()), ROLLBAR_OK);
+
+        assert_eq!(rollbar_shutdown(transport), ROLLBAR_OK);
+        rollbar_destroy_transport(transport);
+
+        let mut items = received(&mock_server);
+        items.sort_by_key(|item| item["data"]["level"].to_string());
+
+        let messages: Vec<_> = items
+            .iter()
+            .map(|item| item["data"]["body"]["message"]["body"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(messages, vec!["", "hello", "h\u{fffd}"]);
+        assert_eq!(items[1]["data"]["body"]["message"]["answer"], 42);
+        assert_eq!(items[1]["data"]["environment"], "test");
+    }
+
+    #[test]
+    fn invalid_extra_is_rejected() {
+        let (token, message, extra) = (string("token"), string("hello"), string("{"));
+
+        let mut transport = ptr::null_mut();
+        assert_eq!(
+            rollbar_create_transport(config(&token), &mut transport),
+            ROLLBAR_OK
+        );
+
+        let code = rollbar_log_with_extra(
+            transport,
+            LevelCompat::Info,
+            message.as_ptr(),
+            extra.as_ptr(),
+        );
+
+        assert_eq!(code, ROLLBAR_ERR_ARGUMENT);
+        assert!(last_error().contains("EOF"));
+
+        rollbar_destroy_transport(transport);
+    }
+
+    #[test]
+    fn traces_are_sent_through_the_c_api() {
+        let mock_server = mock_server();
+
+        let (token, uri) = (string("token"), string(&mock_server.uri()));
+        let (class, message) = (string("std::runtime_error"), string("bad"));
+        let (filename, method) = (string("main.cpp"), string("main"));
+
+        let mut config = config(&token);
+        config.uri = uri.as_ptr();
+
+        let mut transport = ptr::null_mut();
+        assert_eq!(rollbar_create_transport(config, &mut transport), ROLLBAR_OK);
+
+        let trace = rollbar_trace_new(class.as_ptr(), message.as_ptr());
+
+        assert_eq!(
+            rollbar_trace_add_frame(trace, filename.as_ptr(), 12, 0, method.as_ptr()),
+            ROLLBAR_OK
+        );
+        assert_eq!(
+            rollbar_trace_add_frame(trace, ptr::null(), 0, 0, ptr::null()),
+            ROLLBAR_OK
+        );
+        assert_eq!(rollbar_trace_capture(trace, 0), ROLLBAR_OK);
+
+        assert_eq!(
+            rollbar_send_trace(transport, LevelCompat::Error, trace),
+            ROLLBAR_OK
+        );
+        assert_eq!(rollbar_shutdown(transport), ROLLBAR_OK);
+        rollbar_destroy_transport(transport);
+
+        let items = received(&mock_server);
+        let trace = &items[0]["data"]["body"]["trace"];
+
+        assert_eq!(
+            trace["exception"],
+            serde_json::json!({ "class": "std::runtime_error", "message": "bad" })
+        );
+        assert_eq!(
+            trace["frames"][0],
+            serde_json::json!({ "filename": "main.cpp", "lineno": 12, "colno": null, "method": "main" })
+        );
+        assert_eq!(
+            trace["frames"][1],
+            serde_json::json!({ "filename": "<unknown>", "lineno": null, "colno": null })
+        );
+
+        let newest = trace["frames"].as_array().unwrap().last().unwrap();
+
+        assert!(newest["method"]
+            .as_str()
+            .unwrap()
+            .ends_with("traces_are_sent_through_the_c_api"));
+    }
+
+    #[test]
+    fn null_trace_is_an_argument_error() {
+        let token = string("token");
+
+        let mut transport = ptr::null_mut();
+        assert_eq!(
+            rollbar_create_transport(config(&token), &mut transport),
+            ROLLBAR_OK
+        );
+
+        assert_eq!(
+            rollbar_trace_add_frame(ptr::null_mut(), ptr::null(), 1, 1, ptr::null()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert_eq!(
+            rollbar_trace_capture(ptr::null_mut(), 0),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert_eq!(
+            rollbar_send_trace(transport, LevelCompat::Error, ptr::null_mut()),
+            ROLLBAR_ERR_ARGUMENT
+        );
+        assert_eq!(last_error(), "trace is null");
+
+        let trace = rollbar_trace_new(ptr::null(), ptr::null());
+        assert_eq!(
+            rollbar_send_trace(ptr::null_mut(), LevelCompat::Error, trace),
+            ROLLBAR_ERR_ARGUMENT
+        );
+
+        rollbar_trace_free(ptr::null_mut());
+        rollbar_destroy_transport(transport);
+    }
+
+    extern "C" fn ignore_all(_: *const c_char, ignored: *mut c_void) -> bool {
+        unsafe { *(ignored as *mut usize) += 1 };
+
+        true
+    }
+
+    #[test]
+    fn check_ignore_receives_user_data() {
+        let mock_server = mock_server();
+
+        let (token, uri, message) = (string("token"), string(&mock_server.uri()), string("hi"));
+
+        let mut config = config(&token);
+        config.uri = uri.as_ptr();
+
+        let mut transport = ptr::null_mut();
+        assert_eq!(rollbar_create_transport(config, &mut transport), ROLLBAR_OK);
+
+        let mut ignored = 0usize;
+        let user_data = &mut ignored as *mut usize as *mut c_void;
+
+        assert_eq!(
+            rollbar_set_check_ignore(transport, Some(ignore_all), user_data),
+            ROLLBAR_OK
+        );
+        assert_eq!(
+            rollbar_log(transport, LevelCompat::Info, message.as_ptr()),
+            ROLLBAR_OK
+        );
+        assert_eq!(rollbar_shutdown(transport), ROLLBAR_OK);
+        rollbar_destroy_transport(transport);
+
+        assert_eq!(ignored, 1);
+        assert!(received(&mock_server).is_empty());
+    }
+
+    #[derive(Default)]
+    struct Results {
+        statuses: Vec<u16>,
+        errors: usize,
+    }
+
+    extern "C" fn record_result(
+        uuid: *const c_char,
+        status: u16,
+        _: *const c_char,
+        error: *const c_char,
+        results: *mut c_void,
+    ) {
+        let results = unsafe { &mut *(results as *mut Results) };
+
+        assert!(!uuid.is_null());
+
+        results.statuses.push(status);
+        results.errors += usize::from(!error.is_null());
+    }
+
+    #[test]
+    fn on_result_receives_user_data() {
+        let mock_server = mock_server();
+
+        let (token, uri, message) = (string("token"), string(&mock_server.uri()), string("hi"));
+
+        let mut config = config(&token);
+        config.uri = uri.as_ptr();
+
+        let mut transport = ptr::null_mut();
+        assert_eq!(rollbar_create_transport(config, &mut transport), ROLLBAR_OK);
+
+        let mut results = Results::default();
+        let user_data = &mut results as *mut Results as *mut c_void;
+
+        assert_eq!(
+            rollbar_set_on_result(transport, Some(record_result), user_data),
+            ROLLBAR_OK
+        );
+        assert_eq!(
+            rollbar_log(transport, LevelCompat::Info, message.as_ptr()),
+            ROLLBAR_OK
+        );
+        assert_eq!(rollbar_shutdown(transport), ROLLBAR_OK);
+        rollbar_destroy_transport(transport);
+
+        assert_eq!(results.statuses, [200]);
+        assert_eq!(results.errors, 0);
+    }
+
+    #[test]
+    fn stats_and_recent_errors_are_reported() {
+        let mock_server = runtime::block_on(async {
+            let mock_server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .and(path("api/1/item"))
+                .respond_with(ResponseTemplate::new(403).set_body_string("denied"))
+                .mount(&mock_server)
+                .await;
+
+            mock_server
+        })
+        .unwrap();
+
+        let (token, uri, message) = (string("token"), string(&mock_server.uri()), string("hi"));
+
+        let mut config = config(&token);
+        config.uri = uri.as_ptr();
+
+        let mut transport = ptr::null_mut();
+        assert_eq!(rollbar_create_transport(config, &mut transport), ROLLBAR_OK);
+
+        assert_eq!(
+            rollbar_log(transport, LevelCompat::Info, message.as_ptr()),
+            ROLLBAR_OK
+        );
+        assert_eq!(rollbar_shutdown(transport), ROLLBAR_ERR_TRANSPORT);
+
+        let mut stats = StatsCompat {
+            sent: 0,
+            failed: 0,
+            dropped: 0,
+            retried: 0,
+            queue_depth: 0,
+            last_error_status: 0,
+        };
+        assert_eq!(rollbar_stats(transport, &mut stats), ROLLBAR_OK);
+        assert_eq!((stats.sent, stats.failed), (0, 1));
+        assert_eq!(stats.last_error_status, 403);
+
+        let mut buf = [0 as c_char; 512];
+        let mut full_len = 0;
+        assert_eq!(
+            rollbar_recent_errors(transport, buf.as_mut_ptr(), buf.len(), &mut full_len),
+            ROLLBAR_OK
+        );
+
+        let errors: Value =
+            serde_json::from_slice(unsafe { CStr::from_ptr(buf.as_ptr()).to_bytes() }).unwrap();
+        assert_eq!(full_len, errors.to_string().len());
+        assert_eq!(errors[0]["code"], "AccessDenied");
+        assert_eq!(errors[0]["body"], "denied");
+
+        rollbar_destroy_transport(transport);
+    }
+
+    #[test]
+    fn sockets_shut_down_through_libc() {
+        use std::{
+            io::Read,
+            net::{Shutdown, TcpListener, TcpStream},
+        };
+
+        // hyper and wiremock shut sockets down with libc's `shutdown`, which
+        // an unprefixed export would replace in this test binary.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        server
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let mut buf = [0; 1];
+        assert_eq!(server.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn libc_functions_are_not_shadowed() {
+        // An export named `shutdown` would be handed the descriptor as a
+        // transport pointer, and one named `log` the float as one.
+        let code = unsafe { libc::shutdown(-1, libc::SHUT_RDWR) };
+
+        assert_eq!(code, -1);
+        assert_eq!(
+            std::io::Error::last_os_error().raw_os_error(),
+            Some(libc::EBADF)
+        );
+        assert_eq!(std::hint::black_box(std::f64::consts::E).ln(), 1.0);
     }
 }
@@ -0,0 +1,648 @@
+//! Crash records for C and C++ hosts on Linux. A signal handler writes the
+//! signal and raw stack addresses to a spool directory, and the next process
+//! symbolizes them and sends them as critical items.
+//!
+//! Addresses are recorded relative to the module they fall in, so they can
+//! be symbolized again after the binary is reloaded at another address.
+//!
+//! The handler walks frame pointers rather than unwinding, since unwinders
+//! take the loader lock. Code built without frame pointers cuts the trace
+//! short at its first frame.
+
+use crate::{
+    native,
+    types::{Exception, Frame, Item, Level, Trace},
+    Transport,
+};
+
+use ::{
+    libc::{c_int, c_void},
+    once_cell::sync::OnceCell,
+    serde_json::Value,
+    std::{
+        collections::HashMap,
+        ffi::{CStr, CString},
+        fs, io, mem,
+        os::unix::ffi::OsStrExt,
+        path::Path,
+        ptr, slice,
+    },
+};
+
+const SIGNALS: [c_int; 4] = [libc::SIGSEGV, libc::SIGABRT, libc::SIGBUS, libc::SIGFPE];
+const MAX_FRAMES: usize = 64;
+const ALT_STACK_SIZE: usize = 64 * 1024;
+const EXTENSION: &str = "crash";
+
+struct Module {
+    start: usize,
+    end: usize,
+    bias: usize,
+    path: CString,
+}
+
+struct Handler {
+    record: CString,
+    modules: Vec<Module>,
+    probe: Probe,
+}
+
+static HANDLER: OnceCell<Handler> = OnceCell::new();
+
+/// Installs handlers for `SIGSEGV`, `SIGABRT`, `SIGBUS` and `SIGFPE` that
+/// write a record to `spool_dir` and re-raise the signal. Only the first
+/// successful call installs them. Stack overflows are only caught on the
+/// calling thread, which is the only one given an alternate signal stack.
+pub fn install(spool_dir: &Path) -> io::Result<()> {
+    if HANDLER.get().is_some() {
+        return Err(already_installed());
+    }
+
+    fs::create_dir_all(spool_dir)?;
+
+    let record = spool_dir.join(format!("{}.{}", std::process::id(), EXTENSION));
+
+    let handler = Handler {
+        record: CString::new(record.as_os_str().as_bytes())?,
+        modules: modules(),
+        probe: Probe::new()?,
+    };
+
+    // Until the handler is set, a signal only re-raises, so a failed
+    // install can be tried again.
+    unsafe { install_actions()? };
+
+    HANDLER.set(handler).map_err(|_| already_installed())
+}
+
+fn already_installed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "crash handler is already installed",
+    )
+}
+
+unsafe fn install_actions() -> io::Result<()> {
+    unsafe {
+        let stack = Box::leak(vec![0u8; ALT_STACK_SIZE].into_boxed_slice());
+
+        let alt_stack = libc::stack_t {
+            ss_sp: stack.as_mut_ptr() as *mut c_void,
+            ss_flags: 0,
+            ss_size: stack.len(),
+        };
+
+        if libc::sigaltstack(&alt_stack, ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for signal in SIGNALS {
+            let mut action: libc::sigaction = mem::zeroed();
+
+            action.sa_sigaction = handle as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK | libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Only async-signal-safe calls from here on: no allocation, no locks.
+extern "C" fn handle(signal: c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    if let Some(handler) = HANDLER.get() {
+        let mut ips = [0usize; MAX_FRAMES];
+        let len = walk(&handler.probe, context as *const libc::ucontext_t, &mut ips);
+
+        let address = unsafe { (*info).si_addr() } as usize;
+
+        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+        let fd = unsafe { libc::open(handler.record.as_ptr(), flags, 0o600) };
+
+        if fd >= 0 {
+            write_record(fd, signal, address, &handler.modules, &ips[..len]);
+
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    // `SA_RESETHAND` restored the default action, which runs once the
+    // handler returns.
+    unsafe { libc::raise(signal) };
+}
+
+/// The program counter and frame pointer at the time of the signal.
+fn registers(context: *const libc::ucontext_t) -> Option<(usize, usize)> {
+    if context.is_null() {
+        return None;
+    }
+
+    let context = unsafe { &*context };
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            let registers = &context.uc_mcontext.gregs;
+
+            Some((
+                registers[libc::REG_RIP as usize] as usize,
+                registers[libc::REG_RBP as usize] as usize,
+            ))
+        } else if #[cfg(target_arch = "aarch64")] {
+            Some((
+                context.uc_mcontext.pc as usize,
+                context.uc_mcontext.regs[29] as usize,
+            ))
+        } else {
+            let _ = context;
+
+            None
+        }
+    }
+}
+
+/// Fills `ips` with the interrupted instruction followed by the return
+/// addresses found by following saved frame pointers, and returns how many
+/// there are. Every read goes through `probe`, so a bad pointer ends the
+/// walk instead of faulting again.
+fn walk(probe: &Probe, context: *const libc::ucontext_t, ips: &mut [usize]) -> usize {
+    let word = mem::size_of::<usize>();
+
+    let (pc, mut fp) = match registers(context) {
+        Some(registers) => registers,
+        None => return 0,
+    };
+
+    ips[0] = pc;
+    let mut len = 1;
+
+    while len < ips.len() && fp != 0 && fp % word == 0 {
+        let (next, ip) = match (probe.read(fp), probe.read(fp + word)) {
+            (Some(next), Some(ip)) => (next, ip),
+            _ => break,
+        };
+
+        if ip == 0 {
+            break;
+        }
+
+        ips[len] = ip;
+        len += 1;
+
+        // Callers' frames are further up the stack.
+        if next <= fp {
+            break;
+        }
+
+        fp = next;
+    }
+
+    len
+}
+
+/// Reads memory that may not be mapped by passing it to `write(2)`, which
+/// fails with `EFAULT` instead of raising a signal, and reading it back.
+struct Probe {
+    read: c_int,
+    write: c_int,
+}
+
+impl Probe {
+    fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            read: fds[0],
+            write: fds[1],
+        })
+    }
+
+    fn read(&self, address: usize) -> Option<usize> {
+        let size = mem::size_of::<usize>();
+        let mut word = 0usize;
+
+        let written = unsafe { libc::write(self.write, address as *const c_void, size) };
+
+        if written != size as isize {
+            return None;
+        }
+
+        let read = unsafe { libc::read(self.read, &mut word as *mut usize as *mut c_void, size) };
+
+        if read != size as isize {
+            return None;
+        }
+
+        Some(word)
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+/// Writes a record in the line-based format `read` parses.
+fn write_record(fd: c_int, signal: c_int, address: usize, modules: &[Module], ips: &[usize]) {
+    let mut out = Writer::new(fd);
+
+    out.bytes(b"signal ");
+    out.hex(signal as usize);
+    out.bytes(b"\naddress ");
+    out.hex(address);
+    out.bytes(b"\n");
+
+    for (index, module) in modules.iter().enumerate() {
+        out.bytes(b"module ");
+        out.hex(index);
+        out.bytes(b" ");
+        out.bytes(module.path.as_bytes());
+        out.bytes(b"\n");
+    }
+
+    for &ip in ips {
+        out.bytes(b"frame ");
+
+        match modules
+            .iter()
+            .position(|module| module.start <= ip && ip < module.end)
+        {
+            Some(index) => {
+                out.hex(index);
+                out.bytes(b" ");
+                out.hex(ip - modules[index].bias);
+            }
+            None => {
+                out.bytes(b"- ");
+                out.hex(ip);
+            }
+        }
+
+        out.bytes(b"\n");
+    }
+
+    out.flush();
+}
+
+/// A buffered writer to a file descriptor that doesn't allocate.
+struct Writer {
+    fd: c_int,
+    buf: [u8; 512],
+    len: usize,
+}
+
+impl Writer {
+    fn new(fd: c_int) -> Self {
+        Self {
+            fd,
+            buf: [0; 512],
+            len: 0,
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn hex(&mut self, mut n: usize) {
+        let mut digits = [0u8; 16];
+        let mut start = digits.len();
+
+        loop {
+            start -= 1;
+            digits[start] = b"0123456789abcdef"[n % 16];
+            n /= 16;
+
+            if n == 0 {
+                break;
+            }
+        }
+
+        self.bytes(&digits[start..]);
+    }
+
+    fn flush(&mut self) {
+        let mut written = 0;
+
+        while written < self.len {
+            let remaining = &self.buf[written..self.len];
+            let n = unsafe {
+                libc::write(
+                    self.fd,
+                    remaining.as_ptr() as *const c_void,
+                    remaining.len(),
+                )
+            };
+
+            if n <= 0 {
+                break;
+            }
+
+            written += n as usize;
+        }
+
+        self.len = 0;
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Record {
+    signal: c_int,
+    address: usize,
+    modules: HashMap<usize, String>,
+    /// Innermost first, as a module index and offset into it or an
+    /// absolute address.
+    frames: Vec<(Option<usize>, usize)>,
+}
+
+fn read(text: &str) -> Option<Record> {
+    let hex = |n: &str| usize::from_str_radix(n, 16).ok();
+
+    let mut record = Record {
+        signal: 0,
+        address: 0,
+        modules: HashMap::new(),
+        frames: vec![],
+    };
+
+    for line in text.lines() {
+        let (key, rest) = line.split_once(' ')?;
+
+        match key {
+            "signal" => record.signal = hex(rest)? as c_int,
+            "address" => record.address = hex(rest)?,
+            "module" => {
+                let (index, path) = rest.split_once(' ')?;
+                record.modules.insert(hex(index)?, path.to_owned());
+            }
+            "frame" => {
+                let (index, address) = rest.split_once(' ')?;
+                let index = if index == "-" {
+                    None
+                } else {
+                    Some(hex(index)?)
+                };
+
+                record.frames.push((index, hex(address)?));
+            }
+            _ => return None,
+        }
+    }
+
+    if record.signal == 0 {
+        return None;
+    }
+
+    Some(record)
+}
+
+fn signal_name(signal: c_int) -> (&'static str, &'static str) {
+    match signal {
+        libc::SIGSEGV => ("SIGSEGV", "Segmentation fault"),
+        libc::SIGABRT => ("SIGABRT", "Aborted"),
+        libc::SIGBUS => ("SIGBUS", "Bus error"),
+        libc::SIGFPE => ("SIGFPE", "Floating point exception"),
+        _ => ("Signal", "Crashed"),
+    }
+}
+
+/// Symbolizes `record` against the modules loaded in this process. Frames
+/// in modules that aren't loaded keep their module path and offset.
+fn symbolize(record: &Record) -> Trace {
+    let loaded: HashMap<_, _> = modules()
+        .into_iter()
+        .map(|module| (module.path, module.bias))
+        .collect();
+
+    let mut frames: Vec<Frame> = vec![];
+
+    for &(index, address) in record.frames.iter().rev() {
+        let path = index.and_then(|index| record.modules.get(&index));
+
+        let bias = path
+            .and_then(|path| CString::new(path.as_str()).ok())
+            .and_then(|path| loaded.get(&path).copied());
+
+        match (path, bias) {
+            (Some(_), Some(bias)) => frames.extend(native::resolve(bias + address)),
+            (Some(path), None) => frames.push(Frame::new(
+                path.as_str(),
+                None,
+                None,
+                Some(format!("+{:#x}", address)),
+            )),
+            (None, _) => frames.extend(native::resolve(address)),
+        }
+    }
+
+    let (class, description) = signal_name(record.signal);
+
+    let message = match record.signal {
+        libc::SIGABRT => description.to_owned(),
+        _ => format!("{} at {:#x}", description, record.address),
+    };
+
+    Trace::new(frames, Exception::new(class, Some(message)))
+}
+
+/// Sends the records in `spool_dir` as critical items and removes them.
+/// Returns how many were sent; unreadable records are removed as well.
+pub fn report(transport: &Transport, spool_dir: &Path) -> io::Result<usize> {
+    let entries = match fs::read_dir(spool_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+
+    let mut sent = 0;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.extension() != Some(EXTENSION.as_ref()) {
+            continue;
+        }
+
+        let record = fs::read_to_string(&path).ok().and_then(|text| read(&text));
+
+        fs::remove_file(&path)?;
+
+        if let Some(record) = record {
+            let extra = HashMap::from([("signal".to_owned(), Value::from(record.signal))]);
+            let item = Item::from_traces(Level::Critical, vec![symbolize(&record)], extra);
+
+            if transport.send(item).is_ok() {
+                sent += 1;
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+fn modules() -> Vec<Module> {
+    unsafe extern "C" fn push(
+        info: *mut libc::dl_phdr_info,
+        _: usize,
+        modules: *mut c_void,
+    ) -> c_int {
+        let modules = &mut *(modules as *mut Vec<Module>);
+        let info = &*info;
+
+        // The executable itself is listed without a name.
+        let path = if info.dlpi_name.is_null() || *info.dlpi_name == 0 {
+            match std::env::current_exe().map(|path| CString::new(path.as_os_str().as_bytes())) {
+                Ok(Ok(path)) => path,
+                _ => return 0,
+            }
+        } else {
+            CStr::from_ptr(info.dlpi_name).to_owned()
+        };
+
+        let headers = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+
+        let (start, end) = headers
+            .iter()
+            .filter(|header| header.p_type == libc::PT_LOAD)
+            .fold((usize::MAX, 0), |(start, end), header| {
+                let vaddr = header.p_vaddr as usize;
+                (start.min(vaddr), end.max(vaddr + header.p_memsz as usize))
+            });
+
+        if start < end {
+            let bias = info.dlpi_addr as usize;
+
+            modules.push(Module {
+                start: bias + start,
+                end: bias + end,
+                bias,
+                path,
+            });
+        }
+
+        0
+    }
+
+    let mut modules = vec![];
+
+    unsafe { libc::dl_iterate_phdr(Some(push), &mut modules as *mut Vec<Module> as *mut c_void) };
+
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::std::{fs::File, os::unix::io::AsRawFd};
+
+    #[inline(never)]
+    fn crashing_function() -> usize {
+        // An address inside this function, like a return address would be.
+        crashing_function as *const () as usize + 1
+    }
+
+    #[test]
+    fn records_are_symbolized() {
+        let dir = std::env::temp_dir().join(format!("rollbar-crash-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("record.crash");
+        let file = File::create(&path).unwrap();
+
+        let modules = modules();
+        let ips = [crashing_function(), 0x10];
+
+        write_record(file.as_raw_fd(), libc::SIGSEGV, 0xdead, &modules, &ips);
+        drop(file);
+
+        let record = read(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        assert_eq!(record.signal, libc::SIGSEGV);
+        assert_eq!(record.address, 0xdead);
+        assert_eq!(record.frames.len(), 2);
+        assert_eq!(record.frames[1], (None, 0x10));
+
+        let trace = serde_json::to_value(symbolize(&record)).unwrap();
+
+        assert_eq!(trace["exception"]["class"], "SIGSEGV");
+        assert_eq!(
+            trace["exception"]["message"],
+            "Segmentation fault at 0xdead"
+        );
+
+        let newest = trace["frames"].as_array().unwrap().last().unwrap();
+
+        assert!(newest["method"]
+            .as_str()
+            .unwrap()
+            .ends_with("crashing_function"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn probe_reads_only_mapped_memory() {
+        let probe = Probe::new().unwrap();
+        let value = 0x1234_5678usize;
+
+        assert_eq!(probe.read(&value as *const usize as usize), Some(value));
+        assert_eq!(probe.read(8), None);
+    }
+
+    #[test]
+    fn walk_without_context_is_empty() {
+        let probe = Probe::new().unwrap();
+        let mut ips = [0; MAX_FRAMES];
+
+        assert_eq!(walk(&probe, ptr::null(), &mut ips), 0);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn walk_follows_frame_pointers() {
+        let probe = Probe::new().unwrap();
+        let mut ips = [0; MAX_FRAMES];
+
+        let mut context: libc::ucontext_t = unsafe { mem::zeroed() };
+
+        // A frame whose caller's frame pointer is null.
+        let frame = [0usize, 0x2000];
+
+        context.uc_mcontext.gregs[libc::REG_RIP as usize] = 0x1000;
+        context.uc_mcontext.gregs[libc::REG_RBP as usize] = frame.as_ptr() as i64;
+
+        assert_eq!(walk(&probe, &context, &mut ips), 2);
+        assert_eq!(ips[..2], [0x1000, 0x2000]);
+
+        // A frame pointer into unmapped memory.
+        context.uc_mcontext.gregs[libc::REG_RBP as usize] = 8;
+
+        assert_eq!(walk(&probe, &context, &mut ips), 1);
+    }
+
+    #[test]
+    fn malformed_records_are_rejected() {
+        assert_eq!(read(""), None);
+        assert_eq!(read("signal b\nframe"), None);
+        assert_eq!(read("signal zz\n"), None);
+    }
+}
@@ -0,0 +1,198 @@
+use ::{serde_json::Value, std::collections::HashMap};
+
+use crate::types::{Item, Signature};
+
+use uuid::Uuid;
+
+const OCCURRENCE_COUNT: &str = "occurrence_count";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Observation {
+    /// The item has not been seen within the window and should be sent. If a
+    /// previous window for the same signature had expired without being
+    /// flushed, its summary is returned so it can be sent first.
+    First(Option<Box<Item>>),
+    /// The item repeats one already sent within the window and was folded
+    /// into its counter.
+    Folded,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    started: u64,
+    item: Item,
+    repeats: u64,
+}
+
+impl Entry {
+    fn summary(self) -> Option<Item> {
+        if self.repeats == 0 {
+            return None;
+        }
+
+        let mut item = self.item;
+
+        // The summary is a new occurrence, so it can't share the uuid Rollbar
+        // already received with the first one.
+        item.data.uuid = Some(Uuid::new_v4().to_string());
+
+        item.data
+            .custom
+            .get_or_insert_with(HashMap::new)
+            .insert(OCCURRENCE_COUNT.into(), Value::from(self.repeats));
+
+        Some(item)
+    }
+}
+
+/// Folds repeated items seen within `window_ms` of the first occurrence into
+/// a counter. Once the window closes, a single summary occurrence carrying
+/// the number of folded repeats in `custom.occurrence_count` is produced.
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    window_ms: Option<u64>,
+    entries: HashMap<Signature, Entry>,
+}
+
+impl Deduplicator {
+    pub fn new(window_ms: Option<u64>) -> Self {
+        Self {
+            window_ms: window_ms.filter(|window_ms| *window_ms > 0),
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, item: &Item, now: u64) -> Observation {
+        let window_ms = match self.window_ms {
+            Some(window_ms) => window_ms,
+            None => return Observation::First(None),
+        };
+
+        let signature = item.signature();
+
+        if let Some(entry) = self.entries.get_mut(&signature) {
+            if now.saturating_sub(entry.started) < window_ms {
+                entry.repeats += 1;
+
+                return Observation::Folded;
+            }
+        }
+
+        let previous = self.entries.insert(
+            signature,
+            Entry {
+                started: now,
+                item: item.clone(),
+                repeats: 0,
+            },
+        );
+
+        Observation::First(previous.and_then(Entry::summary).map(Box::new))
+    }
+
+    /// Removes every window that has closed by `now`, returning the summaries
+    /// of those that folded at least one repeat.
+    pub fn take_expired(&mut self, now: u64) -> Vec<Item> {
+        let window_ms = match self.window_ms {
+            Some(window_ms) => window_ms,
+            None => return vec![],
+        };
+
+        let expired: Vec<Signature> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| now.saturating_sub(entry.started) >= window_ms)
+            .map(|(signature, _)| signature.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|signature| self.entries.remove(&signature))
+            .filter_map(Entry::summary)
+            .collect()
+    }
+
+    /// Closes every open window regardless of its age.
+    pub fn take_all(&mut self) -> Vec<Item> {
+        self.entries
+            .drain()
+            .filter_map(|(_, entry)| entry.summary())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Level;
+
+    fn item(level: Level, message: &str) -> Item {
+        Item::from((level, message, HashMap::new()))
+    }
+
+    #[test]
+    fn disabled_without_window() {
+        let mut dedup = Deduplicator::new(None);
+
+        let item = item(Level::Error, "boom");
+
+        assert_eq!(dedup.observe(&item, 0), Observation::First(None));
+        assert_eq!(dedup.observe(&item, 1), Observation::First(None));
+        assert!(dedup.take_all().is_empty());
+    }
+
+    #[test]
+    fn folds_repeats_within_window() {
+        let mut dedup = Deduplicator::new(Some(1_000));
+
+        let boom = item(Level::Error, "boom");
+
+        assert_eq!(dedup.observe(&boom, 0), Observation::First(None));
+        assert_eq!(dedup.observe(&boom, 10), Observation::Folded);
+        assert_eq!(dedup.observe(&boom, 999), Observation::Folded);
+
+        let other_level = item(Level::Warning, "boom");
+        assert_eq!(dedup.observe(&other_level, 20), Observation::First(None));
+
+        assert!(dedup.take_expired(999).is_empty());
+
+        let summaries = dedup.take_expired(1_000);
+        assert_eq!(summaries.len(), 1);
+
+        let custom = summaries[0].data.custom.as_ref().unwrap();
+        assert_eq!(custom[OCCURRENCE_COUNT], Value::from(2));
+    }
+
+    #[test]
+    fn separators_in_messages_do_not_collide() {
+        let mut dedup = Deduplicator::new(Some(1_000));
+
+        let joined = item(Level::Error, "a|b");
+
+        let mut fingerprinted = item(Level::Error, "a");
+        fingerprinted.data.fingerprint = Some("b|".into());
+
+        assert_eq!(dedup.observe(&joined, 0), Observation::First(None));
+        assert_eq!(dedup.observe(&fingerprinted, 10), Observation::First(None));
+    }
+
+    #[test]
+    fn expired_window_summary_returned_on_next_occurrence() {
+        let mut dedup = Deduplicator::new(Some(100));
+
+        let boom = item(Level::Error, "boom");
+
+        dedup.observe(&boom, 0);
+        dedup.observe(&boom, 50);
+
+        match dedup.observe(&boom, 150) {
+            Observation::First(Some(summary)) => {
+                let custom = summary.data.custom.unwrap();
+                assert_eq!(custom[OCCURRENCE_COUNT], Value::from(1));
+            }
+            other => panic!("unexpected observation: {:?}", other),
+        }
+
+        assert!(dedup.take_all().is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use ::{serde::Serialize, std::collections::VecDeque};
+
+use crate::transport::Error;
+
+/// A delivery error as it is kept in the history.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Failure {
+    pub timestamp_ms: u64,
+    /// The uuid of the item, when the error concerns one.
+    pub uuid: Option<String>,
+    /// See `Error::code`.
+    pub code: &'static str,
+    pub message: String,
+    /// The HTTP status and response body, when Rollbar refused the item.
+    pub status: Option<u16>,
+    pub body: Option<String>,
+}
+
+impl Failure {
+    pub fn new(error: &Error, uuid: Option<String>, timestamp_ms: u64) -> Self {
+        let rejection = error.rejection();
+
+        Self {
+            timestamp_ms,
+            uuid,
+            code: error.code(),
+            message: error.to_string(),
+            status: rejection.map(|rejection| rejection.status),
+            body: rejection.map(|rejection| rejection.body.clone()),
+        }
+    }
+}
+
+/// Counts of what became of items, as returned by `Transport::stats`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    /// Items Rollbar accepted.
+    pub sent: u64,
+    /// Items that could not be delivered.
+    pub failed: u64,
+    /// Items suppressed by `items_per_minute` or `max_items`.
+    pub dropped: u64,
+    /// Items persisted by an earlier page and queued again.
+    pub retried: u64,
+    /// Items queued but not yet handled by the worker.
+    pub queue_depth: u64,
+    pub last_error: Option<Failure>,
+}
+
+/// Running totals along with the most recent `capacity` failures, oldest
+/// first. Failures nobody was told about are also kept for `shutdown`.
+#[derive(Debug)]
+pub struct History {
+    capacity: usize,
+    failures: VecDeque<(Failure, bool)>,
+    /// Unclaimed failures pushed out of the history.
+    overflow: usize,
+    sent: u64,
+    failed: u64,
+    retried: u64,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            failures: VecDeque::new(),
+            overflow: 0,
+            sent: 0,
+            failed: 0,
+            retried: 0,
+        }
+    }
+
+    pub fn sent(&mut self) {
+        self.sent += 1;
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub fn retried(&mut self) {
+        self.retried += 1;
+    }
+
+    /// Records a failure. `claimed` means it was already handed to someone
+    /// waiting on the item.
+    pub fn failed(&mut self, failure: Failure, claimed: bool) {
+        self.failed += 1;
+
+        if self.capacity == 0 {
+            self.overflow += usize::from(!claimed);
+
+            return;
+        }
+
+        while self.failures.len() >= self.capacity {
+            if let Some((_, false)) = self.failures.pop_front() {
+                self.overflow += 1;
+            }
+        }
+
+        self.failures.push_back((failure, claimed));
+    }
+
+    pub fn recent(&self) -> Vec<Failure> {
+        self.failures
+            .iter()
+            .map(|(failure, _)| failure.clone())
+            .collect()
+    }
+
+    /// Fills in the counts kept here.
+    pub fn stats(&self, stats: &mut Stats) {
+        stats.sent = self.sent;
+        stats.failed = self.failed;
+        stats.retried = self.retried;
+        stats.last_error = self.failures.back().map(|(failure, _)| failure.clone());
+    }
+
+    /// The failures nobody was told about, along with how many more of them
+    /// no longer fit in the history.
+    pub fn unclaimed(&self) -> (Vec<&Failure>, usize) {
+        let failures = self
+            .failures
+            .iter()
+            .filter(|(_, claimed)| !claimed)
+            .map(|(failure, _)| failure)
+            .collect();
+
+        (failures, self.overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(timestamp_ms: u64) -> Failure {
+        Failure::new(&Error::Canceled, None, timestamp_ms)
+    }
+
+    #[test]
+    fn keeps_most_recent_failures() {
+        let mut history = History::new(2);
+
+        history.failed(failure(1), false);
+        history.failed(failure(2), true);
+        history.failed(failure(3), false);
+
+        assert_eq!(history.recent(), vec![failure(2), failure(3)]);
+
+        let (unclaimed, overflow) = history.unclaimed();
+        assert_eq!(unclaimed, vec![&failure(3)]);
+        assert_eq!(overflow, 1);
+
+        let mut stats = Stats::default();
+        history.stats(&mut stats);
+        assert_eq!(stats.failed, 3);
+        assert_eq!(stats.last_error, Some(failure(3)));
+    }
+}
@@ -0,0 +1,39 @@
+use ::std::{fmt, sync::Arc};
+
+use crate::{transport::Error, types::Item};
+
+/// Returns `true` when the fully prepared item should not be sent.
+pub type CheckIgnore = dyn Fn(&Item) -> bool + Send + Sync;
+
+/// Mutates a prepared item before it is filtered and serialized.
+pub type Transform = dyn Fn(&mut Item) + Send + Sync;
+
+/// Observes the outcome of every item the worker tries to send.
+pub type OnResult = dyn Fn(&Delivery) + Send + Sync;
+
+/// What became of an item. `status` and `body` are missing when no response
+/// was received, and `error` is set whenever the item wasn't accepted.
+#[derive(Debug)]
+pub struct Delivery<'a> {
+    pub uuid: Option<&'a str>,
+    pub status: Option<u16>,
+    pub body: Option<&'a str>,
+    pub error: Option<&'a Error>,
+}
+
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub check_ignore: Option<Arc<CheckIgnore>>,
+    pub transforms: Vec<Arc<Transform>>,
+    pub on_result: Option<Arc<OnResult>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("check_ignore", &self.check_ignore.is_some())
+            .field("transforms", &self.transforms.len())
+            .field("on_result", &self.on_result.is_some())
+            .finish()
+    }
+}
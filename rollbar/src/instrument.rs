@@ -0,0 +1,395 @@
+use ::{
+    std::{cell::RefCell, rc::Rc},
+    wasm_bindgen::{prelude::*, JsCast},
+    wasm_bindgen_futures::JsFuture,
+    web_sys::{Element, EventTarget},
+};
+
+use crate::{
+    runtime,
+    telemetry::AutoInstrument,
+    types::{Event, Level, Telemetry},
+    Transport,
+};
+
+/// Marks an `XMLHttpRequest` with the method and url it was opened with.
+const XHR_REQUEST: &str = "__rollbar_request";
+
+#[wasm_bindgen(inline_js = r#"
+export function wrap(target, name, hook) {
+    const original = target && target[name];
+
+    if (typeof original !== "function") {
+        return;
+    }
+
+    target[name] = function (...args) {
+        const result = original.apply(this, args);
+
+        try {
+            hook(this, args, result);
+        } catch (_) {}
+
+        return result;
+    };
+}
+"#)]
+extern "C" {
+    /// Replaces `target[name]` with a function that calls the original and
+    /// then `hook(this, args, result)`.
+    fn wrap(target: &JsValue, name: &str, hook: &js_sys::Function);
+}
+
+type Hook = dyn FnMut(JsValue, js_sys::Array, JsValue);
+
+#[derive(Clone)]
+struct Recorder {
+    transport: Transport,
+    /// Requests to Rollbar itself are not recorded.
+    endpoint: String,
+}
+
+impl Recorder {
+    fn record(&self, level: Level, event: Event) {
+        let telemetry = Telemetry::new(level, event, runtime::now_ms());
+
+        let _ = self.transport.record_telemetry(telemetry);
+    }
+
+    fn network(&self, subtype: &str, request: Request, status_code: Option<u16>) {
+        if request.url.starts_with(&self.endpoint) {
+            return;
+        }
+
+        let level = match status_code {
+            Some(status_code) if status_code < 400 => Level::Info,
+            _ => Level::Error,
+        };
+
+        self.record(
+            level,
+            Event::Network {
+                subtype: subtype.into(),
+                method: request.method,
+                url: request.url,
+                status_code,
+                start_timestamp_ms: request.start_time_ms,
+                end_timestamp_ms: runtime::now_ms(),
+            },
+        );
+    }
+}
+
+struct Request {
+    method: String,
+    url: String,
+    start_time_ms: u64,
+}
+
+/// Installs the instrumentation enabled in `instrument`. Wrapped functions
+/// and listeners stay in place for the life of the page.
+pub fn install(
+    transport: &Transport,
+    endpoint: &str,
+    instrument: AutoInstrument,
+) -> Result<(), JsValue> {
+    let recorder = Recorder {
+        transport: transport.clone(),
+        endpoint: endpoint.to_owned(),
+    };
+
+    let global: JsValue = js_sys::global().into();
+
+    if instrument.log {
+        instrument_console(&recorder, &global)?;
+    }
+
+    if instrument.network {
+        instrument_fetch(&recorder, &global);
+        instrument_xhr(&recorder, &global)?;
+    }
+
+    if instrument.navigation {
+        instrument_history(&recorder, &global)?;
+    }
+
+    if instrument.dom {
+        instrument_clicks(&recorder, &global)?;
+    }
+
+    Ok(())
+}
+
+fn hook(target: &JsValue, name: &str, hook: Box<Hook>) {
+    let hook = Closure::wrap(hook);
+
+    wrap(target, name, hook.as_ref().unchecked_ref());
+
+    hook.forget();
+}
+
+fn instrument_console(recorder: &Recorder, global: &JsValue) -> Result<(), JsValue> {
+    let console = js_sys::Reflect::get(global, &"console".into())?;
+
+    let methods = [
+        ("debug", Level::Debug),
+        ("log", Level::Info),
+        ("info", Level::Info),
+        ("warn", Level::Warning),
+        ("error", Level::Error),
+    ];
+
+    for (name, level) in methods {
+        let recorder = recorder.clone();
+
+        hook(
+            &console,
+            name,
+            Box::new(move |_, args, _| {
+                let message = args
+                    .iter()
+                    .map(|arg| describe(&arg))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                recorder.record(level.clone(), Event::Log { message });
+            }),
+        );
+    }
+
+    Ok(())
+}
+
+fn instrument_fetch(recorder: &Recorder, global: &JsValue) {
+    let recorder = recorder.clone();
+
+    hook(
+        global,
+        "fetch",
+        Box::new(move |_, args, result| {
+            let request = fetch_request(&args.get(0), &args.get(1));
+
+            let promise = match result.dyn_into::<js_sys::Promise>() {
+                Ok(promise) => promise,
+                Err(_) => return,
+            };
+
+            let recorder = recorder.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let status_code = JsFuture::from(promise)
+                    .await
+                    .ok()
+                    .and_then(|response| status_code(&response));
+
+                recorder.network("fetch", request, status_code);
+            });
+        }),
+    );
+}
+
+/// The method and url of a `fetch(input, init)` call, where `input` may be
+/// a url or a `Request`.
+fn fetch_request(input: &JsValue, init: &JsValue) -> Request {
+    let url = match input.as_string() {
+        Some(url) => url,
+        None => property(input, "url")
+            .and_then(|url| url.as_string())
+            .unwrap_or_else(|| describe(input)),
+    };
+
+    let method = property(init, "method")
+        .and_then(|method| method.as_string())
+        .or_else(|| property(input, "method").and_then(|method| method.as_string()))
+        .unwrap_or_else(|| "GET".into());
+
+    Request {
+        method: method.to_uppercase(),
+        url,
+        start_time_ms: runtime::now_ms(),
+    }
+}
+
+fn instrument_xhr(recorder: &Recorder, global: &JsValue) -> Result<(), JsValue> {
+    let prototype = match property(global, "XMLHttpRequest") {
+        Some(constructor) => js_sys::Reflect::get(&constructor, &"prototype".into())?,
+        None => return Ok(()),
+    };
+
+    hook(
+        &prototype,
+        "open",
+        Box::new(|xhr, args, _| {
+            let request = js_sys::Array::of2(&args.get(0), &args.get(1));
+
+            let _ = js_sys::Reflect::set(&xhr, &XHR_REQUEST.into(), &request);
+        }),
+    );
+
+    let recorder = recorder.clone();
+
+    hook(
+        &prototype,
+        "send",
+        Box::new(move |xhr, _, _| {
+            let opened = match property(&xhr, XHR_REQUEST) {
+                Some(opened) => js_sys::Array::from(&opened),
+                None => return,
+            };
+
+            let request = Request {
+                method: describe(&opened.get(0)).to_uppercase(),
+                url: describe(&opened.get(1)),
+                start_time_ms: runtime::now_ms(),
+            };
+
+            let recorder = recorder.clone();
+            let target = xhr.clone();
+
+            let on_loadend = Closure::once_into_js(move |_: JsValue| {
+                recorder.network("xhr", request, status_code(&target));
+            });
+
+            let _ = xhr
+                .unchecked_into::<EventTarget>()
+                .add_event_listener_with_callback("loadend", on_loadend.unchecked_ref());
+        }),
+    );
+
+    Ok(())
+}
+
+fn instrument_history(recorder: &Recorder, global: &JsValue) -> Result<(), JsValue> {
+    let last = Rc::new(RefCell::new(href(global).unwrap_or_default()));
+
+    let navigated = {
+        let recorder = recorder.clone();
+        let global = global.clone();
+
+        move || {
+            let to = match href(&global) {
+                Some(to) => to,
+                None => return,
+            };
+
+            let from = last.replace(to.clone());
+
+            if from != to {
+                recorder.record(Level::Info, Event::Navigation { from, to });
+            }
+        }
+    };
+
+    let history = js_sys::Reflect::get(global, &"history".into())?;
+
+    for name in ["pushState", "replaceState"] {
+        let navigated = navigated.clone();
+
+        hook(&history, name, Box::new(move |_, _, _| navigated()));
+    }
+
+    let on_popstate = Closure::wrap(Box::new(navigated) as Box<dyn FnMut()>);
+
+    global
+        .unchecked_ref::<EventTarget>()
+        .add_event_listener_with_callback("popstate", on_popstate.as_ref().unchecked_ref())?;
+
+    on_popstate.forget();
+
+    Ok(())
+}
+
+fn instrument_clicks(recorder: &Recorder, global: &JsValue) -> Result<(), JsValue> {
+    let document = match property(global, "document") {
+        Some(document) => document,
+        None => return Ok(()),
+    };
+
+    let recorder = recorder.clone();
+
+    let on_click = Closure::wrap(Box::new(move |event: web_sys::Event| {
+        let element = match event
+            .target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+        {
+            Some(element) => element,
+            None => return,
+        };
+
+        recorder.record(
+            Level::Info,
+            Event::Dom {
+                subtype: "click".into(),
+                element: describe_element(&element),
+            },
+        );
+    }) as Box<dyn FnMut(web_sys::Event)>);
+
+    // Listening during capture sees clicks even when a handler stops them
+    // from propagating.
+    document
+        .unchecked_ref::<EventTarget>()
+        .add_event_listener_with_callback_and_bool(
+            "click",
+            on_click.as_ref().unchecked_ref(),
+            true,
+        )?;
+
+    on_click.forget();
+
+    Ok(())
+}
+
+/// A CSS-like description of an element, e.g. `button#save.primary`.
+fn describe_element(element: &Element) -> String {
+    let mut description = element.tag_name().to_lowercase();
+
+    let id = element.id();
+    if !id.is_empty() {
+        description.push('#');
+        description.push_str(&id);
+    }
+
+    for class in element.class_name().split_whitespace() {
+        description.push('.');
+        description.push_str(class);
+    }
+
+    description
+}
+
+fn href(global: &JsValue) -> Option<String> {
+    property(global, "location")
+        .and_then(|location| property(&location, "href"))
+        .and_then(|href| href.as_string())
+}
+
+fn status_code(response: &JsValue) -> Option<u16> {
+    property(response, "status")
+        .and_then(|status| status.as_f64())
+        .map(|status| status as u16)
+        .filter(|status| *status > 0)
+}
+
+/// `object[key]`, if `object` is an object and the property is set.
+fn property(object: &JsValue, key: &str) -> Option<JsValue> {
+    if !object.is_object() && !object.is_function() {
+        return None;
+    }
+
+    js_sys::Reflect::get(object, &key.into())
+        .ok()
+        .filter(|value| !value.is_undefined() && !value.is_null())
+}
+
+/// Strings as they are, anything else as JSON when possible.
+fn describe(value: &JsValue) -> String {
+    if let Some(value) = value.as_string() {
+        return value;
+    }
+
+    js_sys::JSON::stringify(value)
+        .ok()
+        .and_then(|json| json.as_string())
+        .unwrap_or_else(|| format!("{:?}", value))
+}
@@ -1,8 +1,32 @@
+mod dedup;
+mod history;
+mod hooks;
+mod metrics;
+mod pending;
+mod rate_limiter;
 mod runtime;
+mod sender;
+pub mod stack;
+mod telemetry;
 mod transport;
 mod types;
 
-pub use self::transport::{Config, Transport};
+pub use self::{
+    history::{Failure, Stats},
+    hooks::{CheckIgnore, Delivery, OnResult, Transform},
+    sender::{PreparedRequest, ReqwestSender, Response, SendFuture, Sender},
+    transport::{Config, Error, Rejection, Transport},
+    types::{Item, Level},
+};
+
+#[cfg(all(any(test, feature = "testing"), not(target_arch = "wasm32")))]
+pub mod testing;
+
+#[cfg(target_arch = "wasm32")]
+mod instrument;
+
+#[cfg(target_arch = "wasm32")]
+mod persist;
 
 #[cfg(target_arch = "wasm32")]
 mod wasm;
@@ -19,5 +43,11 @@ pub use nodejs::*;
 #[cfg(feature = "c")]
 mod c;
 
+#[cfg(feature = "c")]
+mod native;
+
+#[cfg(all(feature = "c", target_os = "linux"))]
+mod crash;
+
 #[cfg(feature = "c")]
 pub use c::*;
@@ -0,0 +1,451 @@
+use ::std::{collections::BTreeMap, fmt::Write};
+
+#[cfg(feature = "metrics")]
+use ::{
+    prometheus::{core::Desc, proto},
+    std::collections::HashMap,
+};
+
+#[cfg(feature = "metrics")]
+use crate::Transport;
+
+/// Upper bounds, in seconds, of the request duration buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Upper bounds, in bytes, of the payload size buckets.
+const SIZE_BUCKETS: &[f64] = &[1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0];
+
+/// Why an item was never sent.
+#[derive(Debug, Clone, Copy)]
+pub enum Policy {
+    /// `items_per_minute` or `max_items`.
+    RateLimit,
+    /// Folded into an earlier occurrence.
+    Duplicate,
+    /// `ignored_messages` or the `check_ignore` predicate.
+    Ignored,
+}
+
+impl Policy {
+    fn label(self) -> &'static str {
+        match self {
+            Policy::RateLimit => "rate_limit",
+            Policy::Duplicate => "duplicate",
+            Policy::Ignored => "ignored",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub bounds: &'static [f64],
+    /// Observations per bucket, not cumulative. The last one is `+Inf`.
+    pub counts: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Each finite upper bound with the number of observations at or below
+    /// it.
+    pub fn cumulative(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        self.bounds
+            .iter()
+            .zip(&self.counts)
+            .scan(0, |total, (bound, count)| {
+                *total += count;
+
+                Some((*bound, *total))
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sample {
+    Counter(u64),
+    Gauge(u64),
+    Histogram(Histogram),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub label: Option<(&'static str, &'static str)>,
+    pub sample: Sample,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// A named group of metrics, as in OpenMetrics. Counter names leave out
+/// the `_total` suffix of their samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Family {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+    /// The name of the label that tells the metrics apart, if any.
+    pub label: Option<&'static str>,
+    pub metrics: Vec<Metric>,
+}
+
+/// Health counters of the transport itself.
+#[derive(Debug)]
+pub struct Metrics {
+    enqueued: u64,
+    sent: u64,
+    failed: BTreeMap<&'static str, u64>,
+    dropped: BTreeMap<&'static str, u64>,
+    latency: Histogram,
+    payload_size: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            enqueued: 0,
+            sent: 0,
+            failed: BTreeMap::new(),
+            dropped: BTreeMap::new(),
+            latency: Histogram::new(LATENCY_BUCKETS),
+            payload_size: Histogram::new(SIZE_BUCKETS),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn enqueued(&mut self) {
+        self.enqueued += 1;
+    }
+
+    pub fn sent(&mut self) {
+        self.sent += 1;
+    }
+
+    /// Counts a failed item under `reason`, an `Error::code`.
+    pub fn failed(&mut self, reason: &'static str) {
+        *self.failed.entry(reason).or_default() += 1;
+    }
+
+    pub fn dropped(&mut self, policy: Policy) {
+        *self.dropped.entry(policy.label()).or_default() += 1;
+    }
+
+    pub fn request(&mut self, duration_ms: u64, payload_size: usize) {
+        self.latency.observe(duration_ms as f64 / 1000.0);
+        self.payload_size.observe(payload_size as f64);
+    }
+
+    pub fn families(&self, queue_depth: u64) -> Vec<Family> {
+        let counter = |count: u64| Metric {
+            label: None,
+            sample: Sample::Counter(count),
+        };
+
+        let labeled = |counts: &BTreeMap<&'static str, u64>, name: &'static str| {
+            counts
+                .iter()
+                .map(|(value, count)| Metric {
+                    label: Some((name, *value)),
+                    sample: Sample::Counter(*count),
+                })
+                .collect()
+        };
+
+        let histogram = |histogram: &Histogram| Metric {
+            label: None,
+            sample: Sample::Histogram(histogram.clone()),
+        };
+
+        vec![
+            Family {
+                name: "rollbar_items_enqueued",
+                help: "Items queued for delivery.",
+                kind: Kind::Counter,
+                label: None,
+                metrics: vec![counter(self.enqueued)],
+            },
+            Family {
+                name: "rollbar_items_sent",
+                help: "Items Rollbar accepted.",
+                kind: Kind::Counter,
+                label: None,
+                metrics: vec![counter(self.sent)],
+            },
+            Family {
+                name: "rollbar_items_failed",
+                help: "Items that could not be delivered, by error code.",
+                kind: Kind::Counter,
+                label: Some("reason"),
+                metrics: labeled(&self.failed, "reason"),
+            },
+            Family {
+                name: "rollbar_items_dropped",
+                help: "Items that were never sent, by the policy that dropped them.",
+                kind: Kind::Counter,
+                label: Some("policy"),
+                metrics: labeled(&self.dropped, "policy"),
+            },
+            Family {
+                name: "rollbar_queue_depth",
+                help: "Items queued but not yet handled.",
+                kind: Kind::Gauge,
+                label: None,
+                metrics: vec![Metric {
+                    label: None,
+                    sample: Sample::Gauge(queue_depth),
+                }],
+            },
+            Family {
+                name: "rollbar_http_request_duration_seconds",
+                help: "Time taken by requests to the Rollbar API.",
+                kind: Kind::Histogram,
+                label: None,
+                metrics: vec![histogram(&self.latency)],
+            },
+            Family {
+                name: "rollbar_payload_size_bytes",
+                help: "Size of the items sent to the Rollbar API.",
+                kind: Kind::Histogram,
+                label: None,
+                metrics: vec![histogram(&self.payload_size)],
+            },
+        ]
+    }
+}
+
+/// Renders `families` in the OpenMetrics text format.
+pub fn render(families: &[Family]) -> String {
+    let mut text = String::new();
+
+    for family in families {
+        let kind = match family.kind {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        };
+
+        let _ = writeln!(text, "# TYPE {} {}", family.name, kind);
+        let _ = writeln!(text, "# HELP {} {}", family.name, family.help);
+
+        for metric in &family.metrics {
+            let labels = match metric.label {
+                Some((name, value)) => format!("{{{}=\"{}\"}}", name, value),
+                None => String::new(),
+            };
+
+            match &metric.sample {
+                Sample::Counter(count) => {
+                    let _ = writeln!(text, "{}_total{} {}", family.name, labels, count);
+                }
+                Sample::Gauge(value) => {
+                    let _ = writeln!(text, "{}{} {}", family.name, labels, value);
+                }
+                Sample::Histogram(histogram) => {
+                    for (bound, count) in histogram.cumulative() {
+                        let _ = writeln!(
+                            text,
+                            "{}_bucket{{le=\"{:?}\"}} {}",
+                            family.name, bound, count
+                        );
+                    }
+
+                    let _ = writeln!(
+                        text,
+                        "{}_bucket{{le=\"+Inf\"}} {}",
+                        family.name, histogram.count
+                    );
+                    let _ = writeln!(text, "{}_sum {:?}", family.name, histogram.sum);
+                    let _ = writeln!(text, "{}_count {}", family.name, histogram.count);
+                }
+            }
+        }
+    }
+
+    text.push_str("# EOF\n");
+
+    text
+}
+
+/// Exposes the metrics of a transport to a `prometheus::Registry`.
+#[cfg(feature = "metrics")]
+pub struct Collector {
+    transport: Transport,
+    descs: Vec<Desc>,
+}
+
+#[cfg(feature = "metrics")]
+impl Collector {
+    pub fn new(transport: Transport) -> Result<Self, prometheus::Error> {
+        let descs = Metrics::default()
+            .families(0)
+            .iter()
+            .map(|family| {
+                Desc::new(
+                    prometheus_name(family),
+                    family.help.into(),
+                    family.label.into_iter().map(String::from).collect(),
+                    HashMap::new(),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { transport, descs })
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl prometheus::core::Collector for Collector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        match self.transport.metric_families() {
+            Ok(families) => families.iter().map(to_proto).collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+/// Prometheus keeps the `_total` suffix in the name of counters.
+#[cfg(feature = "metrics")]
+fn prometheus_name(family: &Family) -> String {
+    match family.kind {
+        Kind::Counter => format!("{}_total", family.name),
+        _ => family.name.to_owned(),
+    }
+}
+
+// The conversions go through `into` since the `protobuf` feature of
+// `prometheus` swaps `Vec` for `RepeatedField`.
+#[cfg(feature = "metrics")]
+#[allow(clippy::useless_conversion)]
+fn to_proto(family: &Family) -> proto::MetricFamily {
+    let mut proto_family = proto::MetricFamily::default();
+
+    proto_family.set_name(prometheus_name(family));
+    proto_family.set_help(family.help.into());
+    proto_family.set_field_type(match family.kind {
+        Kind::Counter => proto::MetricType::COUNTER,
+        Kind::Gauge => proto::MetricType::GAUGE,
+        Kind::Histogram => proto::MetricType::HISTOGRAM,
+    });
+
+    let metrics: Vec<_> = family
+        .metrics
+        .iter()
+        .map(|metric| {
+            let mut proto_metric = proto::Metric::default();
+
+            if let Some((name, value)) = metric.label {
+                let mut label = proto::LabelPair::default();
+                label.set_name(name.into());
+                label.set_value(value.into());
+
+                proto_metric.set_label(vec![label].into());
+            }
+
+            match &metric.sample {
+                Sample::Counter(count) => {
+                    let mut counter = proto::Counter::default();
+                    counter.set_value(*count as f64);
+
+                    proto_metric.set_counter(counter);
+                }
+                Sample::Gauge(value) => {
+                    let mut gauge = proto::Gauge::default();
+                    gauge.set_value(*value as f64);
+
+                    proto_metric.set_gauge(gauge);
+                }
+                Sample::Histogram(histogram) => {
+                    let buckets: Vec<_> = histogram
+                        .cumulative()
+                        .map(|(bound, count)| {
+                            let mut bucket = proto::Bucket::default();
+                            bucket.set_upper_bound(bound);
+                            bucket.set_cumulative_count(count);
+
+                            bucket
+                        })
+                        .collect();
+
+                    let mut proto_histogram = proto::Histogram::default();
+                    proto_histogram.set_sample_count(histogram.count);
+                    proto_histogram.set_sample_sum(histogram.sum);
+                    proto_histogram.set_bucket(buckets.into());
+
+                    proto_metric.set_histogram(proto_histogram);
+                }
+            }
+
+            proto_metric
+        })
+        .collect();
+
+    proto_family.set_metric(metrics.into());
+
+    proto_family
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_open_metrics() {
+        let mut metrics = Metrics::default();
+
+        metrics.enqueued();
+        metrics.failed("AccessDenied");
+        metrics.dropped(Policy::Duplicate);
+        metrics.request(75, 2000);
+
+        let text = render(&metrics.families(3));
+
+        for line in [
+            "# TYPE rollbar_items_enqueued counter\n",
+            "rollbar_items_enqueued_total 1\n",
+            "rollbar_items_sent_total 0\n",
+            "rollbar_items_failed_total{reason=\"AccessDenied\"} 1\n",
+            "rollbar_items_dropped_total{policy=\"duplicate\"} 1\n",
+            "rollbar_queue_depth 3\n",
+            "rollbar_http_request_duration_seconds_bucket{le=\"0.05\"} 0\n",
+            "rollbar_http_request_duration_seconds_bucket{le=\"0.1\"} 1\n",
+            "rollbar_http_request_duration_seconds_bucket{le=\"+Inf\"} 1\n",
+            "rollbar_http_request_duration_seconds_sum 0.075\n",
+            "rollbar_payload_size_bytes_bucket{le=\"1024.0\"} 0\n",
+            "rollbar_payload_size_bytes_bucket{le=\"4096.0\"} 1\n",
+            "rollbar_payload_size_bytes_count 1\n",
+        ] {
+            assert!(text.contains(line), "missing {:?} in\n{}", line, text);
+        }
+
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
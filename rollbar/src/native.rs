@@ -0,0 +1,114 @@
+//! Capture of the native call stack for the C API.
+
+use crate::types::Frame;
+
+use ::std::ffi::c_void;
+
+/// Captures the calling thread's stack as symbolicated frames, oldest first.
+/// Frames up to and including the function at `entry` are left out, as are
+/// `skip` frames beyond it; if `entry` isn't on the stack only the unwinder's
+/// own frames are. Frames inlined into one another are reported
+/// separately, and symbols that can't be resolved keep their address as the
+/// method.
+#[inline(never)]
+pub fn capture(entry: *mut c_void, skip: usize) -> Vec<Frame> {
+    // Innermost first, each paired with whether it is `entry` or this
+    // function.
+    let mut frames = vec![];
+
+    backtrace::trace(|frame| {
+        let address = frame.symbol_address();
+        let marker = address == entry || address == capture as *mut c_void;
+
+        let mut resolved = false;
+
+        backtrace::resolve_frame(frame, |symbol| {
+            resolved = true;
+            frames.push((marker, self::frame(symbol, frame.ip())));
+        });
+
+        if !resolved {
+            frames.push((marker, unresolved(frame.ip())));
+        }
+
+        true
+    });
+
+    let start = frames
+        .iter()
+        .rposition(|(marker, _)| *marker)
+        .map_or(0, |marker| marker + 1);
+
+    frames
+        .into_iter()
+        .skip(start + skip)
+        .rev()
+        .map(|(_, frame)| frame)
+        .collect()
+}
+
+/// Symbolicates `address` in this process, oldest frame first when
+/// functions were inlined into one another.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn resolve(address: usize) -> Vec<Frame> {
+    let mut frames = vec![];
+
+    backtrace::resolve(address as *mut c_void, |symbol| {
+        frames.push(frame(symbol, address as *mut c_void));
+    });
+
+    if frames.is_empty() {
+        frames.push(unresolved(address as *mut c_void));
+    }
+
+    frames.reverse();
+    frames
+}
+
+fn frame(symbol: &backtrace::Symbol, ip: *mut c_void) -> Frame {
+    let filename = symbol
+        .filename()
+        .map(|filename| filename.display().to_string())
+        .unwrap_or_else(|| "<unknown>".into());
+
+    // `{:#}` leaves off the hash Rust appends to mangled names.
+    let method = symbol
+        .name()
+        .map(|name| format!("{:#}", name))
+        .unwrap_or_else(|| format!("{:?}", ip));
+
+    Frame::new(filename, symbol.lineno(), symbol.colno(), Some(method))
+}
+
+fn unresolved(ip: *mut c_void) -> Frame {
+    Frame::new("<unknown>", None, None, Some(format!("{:?}", ip)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline(never)]
+    fn outer() -> Vec<Frame> {
+        inner()
+    }
+
+    #[inline(never)]
+    fn inner() -> Vec<Frame> {
+        capture(inner as *mut c_void, 0)
+    }
+
+    #[test]
+    fn caller_is_the_newest_frame() {
+        let frames = outer();
+
+        let newest = serde_json::to_value(frames.last().unwrap()).unwrap();
+
+        assert!(newest["method"]
+            .as_str()
+            .unwrap()
+            .ends_with("native::tests::outer"));
+        assert!(newest["filename"].as_str().unwrap().ends_with("native.rs"));
+        assert!(newest["lineno"].is_u64());
+    }
+}
@@ -1,17 +1,28 @@
-use ::{neon::prelude::*, serde_json::Value, std::collections::HashMap};
+use ::{
+    neon::{prelude::*, types::Value as _},
+    serde_json::Value,
+    std::{cell::RefCell, collections::HashMap, sync::Arc, time::Duration},
+};
 
 use crate::{
-    types::{Item, Level},
+    runtime, stack,
+    transport::Error,
+    types::{Item, Level, Trace},
     Config, Transport,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Instance {
     transport: Transport,
+    check_ignore: RefCell<Option<Root<JsFunction>>>,
+    transforms: RefCell<Vec<Root<JsFunction>>>,
 }
 
 impl Finalize for Instance {}
 
+/// How long the process handlers wait for their report to be delivered.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(3);
+
 impl Instance {
     pub fn from_config(mut cx: FunctionContext) -> JsResult<JsBox<Instance>> {
         let input: Handle<JsValue> = cx.argument(0)?;
@@ -19,9 +30,438 @@ impl Instance {
         let config: Config =
             neon_serde2::from_value(&mut cx, input).or_else(|e| cx.throw_error(e.to_string()))?;
 
+        let capture_uncaught = config.capture_uncaught;
+        let capture_unhandled_rejections = config.capture_unhandled_rejections;
+
         let transport = Transport::new(config).or_else(|e| cx.throw_error(e.to_string()))?;
 
-        Ok(cx.boxed(Instance { transport }))
+        let instance = cx.boxed(Instance {
+            transport,
+            check_ignore: RefCell::new(None),
+            transforms: RefCell::new(vec![]),
+        });
+
+        if capture_uncaught {
+            Self::listen(
+                instance,
+                "uncaughtException",
+                Self::on_uncaught_exception,
+                &mut cx,
+            )?;
+        }
+
+        if capture_unhandled_rejections {
+            Self::listen(
+                instance,
+                "unhandledRejection",
+                Self::on_unhandled_rejection,
+                &mut cx,
+            )?;
+        }
+
+        Ok(instance)
+    }
+
+    /// Adds `handler` as a `process` listener for `event`, with the instance
+    /// bound as `this`.
+    fn listen<'a>(
+        instance: Handle<JsBox<Self>>,
+        event: &str,
+        handler: fn(FunctionContext) -> JsResult<JsUndefined>,
+        cx: &mut FunctionContext<'a>,
+    ) -> NeonResult<()> {
+        let handler = JsFunction::new(cx, handler)?;
+        let bind = handler
+            .get(cx, "bind")?
+            .downcast_or_throw::<JsFunction, _>(cx)?;
+        let handler = bind.call(cx, handler, vec![instance])?;
+
+        let process = cx
+            .global()
+            .get(cx, "process")?
+            .downcast_or_throw::<JsObject, _>(cx)?;
+        let on = process
+            .get(cx, "on")?
+            .downcast_or_throw::<JsFunction, _>(cx)?;
+
+        let event = cx.string(event);
+        on.call(cx, process, vec![event.upcast(), handler])?;
+
+        Ok(())
+    }
+
+    /// Reports the exception and waits for it to be delivered. If no other
+    /// listener is handling uncaught exceptions, the error is printed and the
+    /// process exits with code 1, as Node would have done.
+    fn on_uncaught_exception(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let error: Handle<JsValue> = cx.argument(0)?;
+
+        Self::report_blocking(instance, error, &mut cx)?;
+
+        Self::exit_if_unhandled("uncaughtException", error, &mut cx)?;
+
+        Ok(cx.undefined())
+    }
+
+    /// Reports the rejection reason and waits for it to be delivered. If no
+    /// other listener is handling unhandled rejections, the reason is printed
+    /// and the process exits with code 1, as Node's default
+    /// `--unhandled-rejections=throw` would have done.
+    fn on_unhandled_rejection(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let reason: Handle<JsValue> = cx.argument(0)?;
+
+        Self::report_blocking(instance, reason, &mut cx)?;
+
+        Self::exit_if_unhandled("unhandledRejection", reason, &mut cx)?;
+
+        Ok(cx.undefined())
+    }
+
+    /// Prints `error` and exits with code 1 if this instance's listener is
+    /// the only one for `event`, since adding it took away Node's default.
+    fn exit_if_unhandled<'a>(
+        event: &str,
+        error: Handle<'a, JsValue>,
+        cx: &mut FunctionContext<'a>,
+    ) -> NeonResult<()> {
+        let process = cx
+            .global()
+            .get(cx, "process")?
+            .downcast_or_throw::<JsObject, _>(cx)?;
+
+        let listener_count = process
+            .get(cx, "listenerCount")?
+            .downcast_or_throw::<JsFunction, _>(cx)?;
+        let event = cx.string(event);
+        let listeners = listener_count
+            .call(cx, process, vec![event])?
+            .downcast_or_throw::<JsNumber, _>(cx)?
+            .value(cx);
+
+        if listeners <= 1.0 {
+            let console = cx
+                .global()
+                .get(cx, "console")?
+                .downcast_or_throw::<JsObject, _>(cx)?;
+            let print = console
+                .get(cx, "error")?
+                .downcast_or_throw::<JsFunction, _>(cx)?;
+            print.call(cx, console, vec![error])?;
+
+            let exit = process
+                .get(cx, "exit")?
+                .downcast_or_throw::<JsFunction, _>(cx)?;
+            let code = cx.number(1);
+            exit.call(cx, process, vec![code])?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports `error` at `critical` level, blocking the JS thread until it
+    /// is delivered or `CAPTURE_TIMEOUT` has passed. Failures are printed
+    /// rather than thrown, since there is nobody left to catch them.
+    fn report_blocking<'a>(
+        instance: Handle<JsBox<Self>>,
+        error: Handle<'a, JsValue>,
+        cx: &mut FunctionContext<'a>,
+    ) -> NeonResult<()> {
+        let item = match Self::item(instance, Level::Critical, error, HashMap::new(), cx)? {
+            Some(item) => item,
+            None => return Ok(()),
+        };
+
+        let result = runtime::block_on(instance.transport.deliver_within(item, CAPTURE_TIMEOUT))
+            .map_err(Error::from)
+            .and_then(|result| result);
+
+        if let Err(error) = result {
+            eprintln!("unable to report to Rollbar: {}", error);
+        }
+
+        Ok(())
+    }
+
+    /// Builds an item from a message or thrown value and runs it through the
+    /// pipeline. Returns `None` if the item should be ignored.
+    fn item<'a>(
+        instance: Handle<JsBox<Self>>,
+        level: Level,
+        message: Handle<'a, JsValue>,
+        extra: HashMap<String, Value>,
+        cx: &mut FunctionContext<'a>,
+    ) -> NeonResult<Option<Item>> {
+        let mut item = if is_error(message, cx)? {
+            let traces = stack::chain(message, |error| read_error(error, cx))?;
+
+            Item::from_traces(level, traces, extra)
+        } else {
+            let message = message.to_string(cx)?.value(cx);
+
+            Item::from((level, message, extra))
+        };
+
+        instance
+            .transport
+            .prepare(&mut item)
+            .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        let item = Self::transform(instance, item, cx)?;
+
+        if Self::is_ignored(instance, &item, cx)? {
+            return Ok(None);
+        }
+
+        Ok(Some(item))
+    }
+
+    pub fn set_check_ignore(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let callback = match cx.argument_opt(0) {
+            Some(callback) if !callback.is_a::<JsUndefined, _>(&mut cx) => {
+                let callback = callback.downcast_or_throw::<JsFunction, _>(&mut cx)?;
+
+                Some(callback.root(&mut cx))
+            }
+            _ => None,
+        };
+
+        if let Some(previous) = instance.check_ignore.replace(callback) {
+            previous.drop(&mut cx);
+        }
+
+        Ok(cx.undefined())
+    }
+
+    /// Registers a function that is called with `{ uuid, status, body, error }`
+    /// for every item the transport tries to send. Pass `undefined` to remove
+    /// it.
+    pub fn set_on_result(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let callback = match cx.argument_opt(0) {
+            Some(callback) if !callback.is_a::<JsUndefined, _>(&mut cx) => {
+                callback.downcast_or_throw::<JsFunction, _>(&mut cx)?
+            }
+            _ => {
+                instance
+                    .transport
+                    .clear_on_result()
+                    .or_else(|e| cx.throw_error(e.to_string()))?;
+
+                return Ok(cx.undefined());
+            }
+        };
+
+        let callback = Arc::new(callback.root(&mut cx));
+
+        // The observer shouldn't keep the process alive on its own.
+        let mut queue = cx.queue();
+        queue.unref(&mut cx);
+
+        instance
+            .transport
+            .set_on_result(move |delivery| {
+                let callback = callback.clone();
+                let uuid = delivery.uuid.map(str::to_owned);
+                let status = delivery.status;
+                let body = delivery.body.map(str::to_owned);
+                let error = delivery
+                    .error
+                    .map(|error| (error.to_string(), error.code()));
+
+                let sent = queue.try_send(move |mut cx| {
+                    let result = cx.empty_object();
+
+                    if let Some(uuid) = uuid {
+                        let uuid = cx.string(uuid);
+                        result.set(&mut cx, "uuid", uuid)?;
+                    }
+
+                    if let Some(status) = status {
+                        let status = cx.number(status);
+                        result.set(&mut cx, "status", status)?;
+                    }
+
+                    if let Some(body) = body {
+                        let body = cx.string(body);
+                        result.set(&mut cx, "body", body)?;
+                    }
+
+                    if let Some((message, code)) = error {
+                        let error = cx.error(message)?;
+                        let code = cx.string(code);
+                        error.set(&mut cx, "code", code)?;
+                        result.set(&mut cx, "error", error)?;
+                    }
+
+                    let this = cx.undefined();
+                    callback
+                        .to_inner(&mut cx)
+                        .call(&mut cx, this, vec![result])?;
+
+                    Ok(())
+                });
+
+                if let Err(error) = sent {
+                    eprintln!("unable to report delivery result: {}", error);
+                }
+            })
+            .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        Ok(cx.undefined())
+    }
+
+    pub fn set_payload(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let input: Handle<JsValue> = cx.argument(0)?;
+
+        let payload: Value =
+            neon_serde2::from_value(&mut cx, input).or_else(|e| cx.throw_error(e.to_string()))?;
+
+        instance
+            .transport
+            .set_payload(payload)
+            .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        Ok(cx.undefined())
+    }
+
+    pub fn merge_payload(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let input: Handle<JsValue> = cx.argument(0)?;
+
+        let payload: Value =
+            neon_serde2::from_value(&mut cx, input).or_else(|e| cx.throw_error(e.to_string()))?;
+
+        instance
+            .transport
+            .merge_payload(payload)
+            .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        Ok(cx.undefined())
+    }
+
+    pub fn add_transform(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let transform = cx.argument::<JsFunction>(0)?.root(&mut cx);
+
+        instance.transforms.borrow_mut().push(transform);
+
+        Ok(cx.undefined())
+    }
+
+    /// Runs the JS transforms in order. Each receives the item as a plain
+    /// object and may either mutate it or return a replacement.
+    fn transform<'a>(
+        instance: Handle<JsBox<Self>>,
+        item: Item,
+        cx: &mut FunctionContext<'a>,
+    ) -> NeonResult<Item> {
+        let transforms: Vec<Handle<JsFunction>> = instance
+            .transforms
+            .borrow()
+            .iter()
+            .map(|transform| transform.to_inner(cx))
+            .collect();
+
+        if transforms.is_empty() {
+            return Ok(item);
+        }
+
+        let mut value =
+            neon_serde2::to_value(cx, &item).or_else(|e| cx.throw_error(e.to_string()))?;
+
+        for transform in transforms {
+            let this = cx.undefined();
+            let result = transform.call(cx, this, vec![value])?;
+
+            if !result.is_a::<JsUndefined, _>(cx) {
+                value = result;
+            }
+        }
+
+        neon_serde2::from_value(cx, value).or_else(|e| cx.throw_error(e.to_string()))
+    }
+
+    fn is_ignored<'a>(
+        instance: Handle<JsBox<Self>>,
+        item: &Item,
+        cx: &mut FunctionContext<'a>,
+    ) -> NeonResult<bool> {
+        let ignored = instance
+            .transport
+            .is_ignored(item)
+            .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        if ignored {
+            return Ok(true);
+        }
+
+        let callback = match &*instance.check_ignore.borrow() {
+            Some(callback) => callback.to_inner(cx),
+            None => return Ok(false),
+        };
+
+        let item = neon_serde2::to_value(cx, item).or_else(|e| cx.throw_error(e.to_string()))?;
+
+        let this = cx.undefined();
+        let result = callback.call(cx, this, vec![item])?;
+
+        let ignored = Self::is_truthy(result, cx)?;
+
+        if ignored {
+            instance.transport.count_ignored();
+        }
+
+        Ok(ignored)
+    }
+
+    /// Whether JS would treat `value` as true, as the browser binding does.
+    fn is_truthy<'a>(value: Handle<'a, JsValue>, cx: &mut FunctionContext<'a>) -> NeonResult<bool> {
+        let boolean = cx
+            .global()
+            .get(cx, "Boolean")?
+            .downcast_or_throw::<JsFunction, _>(cx)?;
+        let this = cx.undefined();
+
+        Ok(boolean
+            .call(cx, this, vec![value])?
+            .downcast_or_throw::<JsBoolean, _>(cx)?
+            .value(cx))
+    }
+
+    /// Returns counts of sent, failed, dropped and retried items, the queue
+    /// depth and the latest error.
+    pub fn stats(mut cx: FunctionContext) -> JsResult<JsValue> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let stats = instance
+            .transport
+            .stats()
+            .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        neon_serde2::to_value(&mut cx, &stats).or_else(|e| cx.throw_error(e.to_string()))
+    }
+
+    /// Returns the most recent delivery errors, oldest first.
+    pub fn recent_errors(mut cx: FunctionContext) -> JsResult<JsValue> {
+        let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
+
+        let errors = instance
+            .transport
+            .recent_errors()
+            .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        neon_serde2::to_value(&mut cx, &errors).or_else(|e| cx.throw_error(e.to_string()))
     }
 
     pub fn shutdown(mut cx: FunctionContext) -> JsResult<JsUndefined> {
@@ -40,28 +480,52 @@ impl Instance {
         level: Level,
         start_arg_idx: i32,
         mut cx: FunctionContext<'a>,
-    ) -> JsResult<'a, JsUndefined> {
-        let message: Handle<JsString> = cx.argument(start_arg_idx)?;
+    ) -> JsResult<'a, JsObject> {
+        let message: Handle<JsValue> = cx.argument(start_arg_idx)?;
 
         let extra: Option<Handle<JsValue>> = cx.argument_opt(start_arg_idx + 1);
 
-        let extra: HashMap<String, Value> = if let Some(extra) = extra {
-            neon_serde2::from_value(&mut cx, extra).or_else(|e| cx.throw_error(e.to_string()))?
-        } else {
-            HashMap::new()
+        let extra: HashMap<String, Value> = match extra {
+            Some(extra)
+                if !extra.is_a::<JsUndefined, _>(&mut cx) && !extra.is_a::<JsNull, _>(&mut cx) =>
+            {
+                neon_serde2::from_value(&mut cx, extra)
+                    .or_else(|e| cx.throw_error(e.to_string()))?
+            }
+            _ => HashMap::new(),
         };
 
-        let item = Item::from((level, message.value(&mut cx), extra));
+        let item = Self::item(instance, level, message, extra, &mut cx)?;
 
-        instance
-            .transport
-            .send(item)
-            .or_else(|e| cx.throw_error(e.to_string()))?;
+        let (deferred, promise) = Deferred::new(&mut cx)?;
 
-        Ok(cx.undefined())
+        let item = match item {
+            Some(item) => item,
+            None => {
+                deferred.settle(&mut cx, Ok(None))?;
+
+                return Ok(promise);
+            }
+        };
+
+        let queue = cx.queue();
+        let transport = instance.transport.clone();
+
+        runtime::spawn(async move {
+            let result = transport.deliver(item).await;
+
+            let settled = queue.try_send(move |mut cx| deferred.settle(&mut cx, result));
+
+            if let Err(error) = settled {
+                eprintln!("unable to settle delivery promise: {}", error);
+            }
+        })
+        .or_else(|e| cx.throw_error(e.to_string()))?;
+
+        Ok(promise)
     }
 
-    pub fn log(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    pub fn log(mut cx: FunctionContext) -> JsResult<JsObject> {
         let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
 
         let level: Handle<JsString> = cx.argument(0)?;
@@ -70,37 +534,180 @@ impl Instance {
         Self::log_with(instance, level, 1, cx)
     }
 
-    pub fn debug(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    pub fn debug(mut cx: FunctionContext) -> JsResult<JsObject> {
         let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
 
         Self::log_with(instance, Level::Debug, 0, cx)
     }
 
-    pub fn info(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    pub fn info(mut cx: FunctionContext) -> JsResult<JsObject> {
         let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
 
         Self::log_with(instance, Level::Info, 0, cx)
     }
 
-    pub fn warning(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    pub fn warning(mut cx: FunctionContext) -> JsResult<JsObject> {
         let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
 
         Self::log_with(instance, Level::Warning, 0, cx)
     }
 
-    pub fn error(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    pub fn error(mut cx: FunctionContext) -> JsResult<JsObject> {
         let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
 
         Self::log_with(instance, Level::Error, 0, cx)
     }
 
-    pub fn critical(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    pub fn critical(mut cx: FunctionContext) -> JsResult<JsObject> {
         let instance = cx.this().downcast_or_throw::<JsBox<Instance>, _>(&mut cx)?;
 
         Self::log_with(instance, Level::Critical, 0, cx)
     }
 }
 
+/// Whether `value` should be reported as a trace: an `Error`, or any object
+/// with a `stack` such as errors from another realm.
+fn is_error<'a, C: Context<'a>>(value: Handle<JsValue>, cx: &mut C) -> NeonResult<bool> {
+    if value.is_a::<JsError, _>(cx) {
+        return Ok(true);
+    }
+
+    match value.downcast::<JsObject, _>(cx) {
+        Ok(object) => Ok(object.get(cx, "stack")?.is_a::<JsString, _>(cx)),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Reads the trace of an error and its `cause`. Anything thrown that isn't
+/// an object becomes a plain `Error` with its string form as the message.
+fn read_error<'a, C: Context<'a>>(
+    error: Handle<'a, JsValue>,
+    cx: &mut C,
+) -> NeonResult<(Trace, Option<Handle<'a, JsValue>>)> {
+    let error = match error.downcast::<JsObject, _>(cx) {
+        Ok(error) => error,
+        Err(_) => {
+            let message = error.to_string(cx)?.value(cx);
+
+            return Ok((stack::trace("Error", &message, None), None));
+        }
+    };
+
+    let name = string_property(error, "name", cx)?.unwrap_or_default();
+    let message = string_property(error, "message", cx)?.unwrap_or_default();
+    let stack = string_property(error, "stack", cx)?;
+
+    let cause = error.get(cx, "cause")?;
+    let cause = if cause.is_a::<JsUndefined, _>(cx) || cause.is_a::<JsNull, _>(cx) {
+        None
+    } else {
+        Some(cause)
+    };
+
+    Ok((stack::trace(&name, &message, stack.as_deref()), cause))
+}
+
+fn string_property<'a, C: Context<'a>>(
+    object: Handle<JsObject>,
+    key: &str,
+    cx: &mut C,
+) -> NeonResult<Option<String>> {
+    let value = object.get(cx, key)?;
+
+    match value.downcast::<JsString, _>(cx) {
+        Ok(value) => Ok(Some(value.value(cx))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The `resolve` and `reject` functions of a JS `Promise`, kept alive until
+/// the transport reports back on the JS thread.
+struct Deferred {
+    resolve: Root<JsFunction>,
+    reject: Root<JsFunction>,
+}
+
+impl Deferred {
+    fn new<'a>(cx: &mut FunctionContext<'a>) -> NeonResult<(Self, Handle<'a, JsObject>)> {
+        let resolvers = cx.empty_object();
+
+        let executor = JsFunction::new(cx, capture_resolvers)?;
+        let bind = executor
+            .get(cx, "bind")?
+            .downcast_or_throw::<JsFunction, _>(cx)?;
+        let executor = bind.call(cx, executor, vec![resolvers])?;
+
+        let promise = cx
+            .global()
+            .get(cx, "Promise")?
+            .downcast_or_throw::<JsFunction, _>(cx)?
+            .construct(cx, vec![executor])?;
+
+        let resolve = resolvers
+            .get(cx, "resolve")?
+            .downcast_or_throw::<JsFunction, _>(cx)?
+            .root(cx);
+        let reject = resolvers
+            .get(cx, "reject")?
+            .downcast_or_throw::<JsFunction, _>(cx)?
+            .root(cx);
+
+        Ok((Self { resolve, reject }, promise))
+    }
+
+    /// Resolves with the occurrence uuid (or `undefined` if the item was not
+    /// sent), or rejects with an `Error` whose `code` names the failure.
+    fn settle<'a, C: Context<'a>>(
+        self,
+        cx: &mut C,
+        result: Result<Option<String>, Error>,
+    ) -> NeonResult<()> {
+        let this = cx.undefined();
+
+        match result {
+            Ok(uuid) => {
+                let uuid = match uuid {
+                    Some(uuid) => cx.string(uuid).upcast::<JsValue>(),
+                    None => cx.undefined().upcast(),
+                };
+
+                self.reject.drop(cx);
+                self.resolve.into_inner(cx).call(cx, this, vec![uuid])?;
+            }
+            Err(error) => {
+                let rejection = cx.error(error.to_string())?;
+                let code = cx.string(error.code());
+                rejection.set(cx, "code", code)?;
+
+                if let Some(refused) = error.rejection() {
+                    let status = cx.number(refused.status);
+                    let body = cx.string(&refused.body);
+                    rejection.set(cx, "status", status)?;
+                    rejection.set(cx, "body", body)?;
+                }
+
+                self.resolve.drop(cx);
+                self.reject.into_inner(cx).call(cx, this, vec![rejection])?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A `Promise` executor, bound to an object that receives its resolvers.
+fn capture_resolvers(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let resolvers = cx.this();
+
+    let resolve = cx.argument::<JsFunction>(0)?;
+    let reject = cx.argument::<JsFunction>(1)?;
+
+    resolvers.set(&mut cx, "resolve", resolve)?;
+    resolvers.set(&mut cx, "reject", reject)?;
+
+    Ok(cx.undefined())
+}
+
 #[neon::main]
 pub fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("fromConfig", Instance::from_config)?;
@@ -111,6 +718,13 @@ pub fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("error", Instance::error)?;
     cx.export_function("critical", Instance::critical)?;
     cx.export_function("shutdown", Instance::shutdown)?;
+    cx.export_function("stats", Instance::stats)?;
+    cx.export_function("recentErrors", Instance::recent_errors)?;
+    cx.export_function("setCheckIgnore", Instance::set_check_ignore)?;
+    cx.export_function("setOnResult", Instance::set_on_result)?;
+    cx.export_function("addTransform", Instance::add_transform)?;
+    cx.export_function("setPayload", Instance::set_payload)?;
+    cx.export_function("mergePayload", Instance::merge_payload)?;
 
     Ok(())
 }
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::Item;
+
+/// An item waiting to be delivered, along with when it was first queued.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Pending {
+    pub queued_ms: u64,
+    pub item: Item,
+}
+
+impl Pending {
+    pub fn new(item: Item, queued_ms: u64) -> Self {
+        Self { queued_ms, item }
+    }
+
+    pub fn uuid(&self) -> Option<&str> {
+        self.item.data.uuid.as_deref()
+    }
+}
+
+/// Picks which items left over from an earlier session are worth sending:
+/// the most recent `max_items` queued within `max_age_ms` of `now`, oldest
+/// first.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub fn replayable(
+    mut records: Vec<Pending>,
+    now: u64,
+    max_age_ms: u64,
+    max_items: usize,
+) -> Vec<Pending> {
+    records.retain(|record| now.saturating_sub(record.queued_ms) <= max_age_ms);
+    records.sort_by_key(|record| record.queued_ms);
+
+    let skip = records.len().saturating_sub(max_items);
+
+    records.split_off(skip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Level;
+
+    fn pending(message: &str, queued_ms: u64) -> Pending {
+        Pending::new(
+            Item::from((Level::Error, message, Default::default())),
+            queued_ms,
+        )
+    }
+
+    #[test]
+    fn replays_recent_items_oldest_first() {
+        let records = vec![
+            pending("newest", 900),
+            pending("expired", 100),
+            pending("oldest", 500),
+            pending("middle", 700),
+        ];
+
+        let replayed = replayable(records, 1_000, 500, 2);
+
+        assert_eq!(
+            replayed,
+            vec![pending("middle", 700), pending("newest", 900)]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use ::{wasm_bindgen::prelude::*, wasm_bindgen_futures::JsFuture};
+
+use crate::pending::Pending;
+
+// Records are kept as JSON strings keyed by item uuid, in IndexedDB when the
+// browser allows it and in a single localStorage entry otherwise.
+#[wasm_bindgen(inline_js = r#"
+const DATABASE = "rollbar";
+const STORE = "pending";
+const LOCAL_STORAGE_KEY = "rollbar.pending";
+
+let database;
+
+function open() {
+    if (!database) {
+        database = new Promise((resolve, reject) => {
+            const request = indexedDB.open(DATABASE, 1);
+
+            request.onupgradeneeded = () => request.result.createObjectStore(STORE);
+            request.onsuccess = () => resolve(request.result);
+            request.onerror = () => reject(request.error);
+        }).catch(() => null);
+    }
+
+    return database;
+}
+
+function run(db, mode, operation) {
+    return new Promise((resolve, reject) => {
+        const transaction = db.transaction(STORE, mode);
+        const request = operation(transaction.objectStore(STORE));
+
+        transaction.oncomplete = () => resolve(request.result);
+        transaction.onerror = () => reject(transaction.error);
+    });
+}
+
+function readLocal() {
+    try {
+        return JSON.parse(localStorage.getItem(LOCAL_STORAGE_KEY)) || {};
+    } catch (_) {
+        return {};
+    }
+}
+
+function writeLocal(records) {
+    try {
+        localStorage.setItem(LOCAL_STORAGE_KEY, JSON.stringify(records));
+    } catch (_) {}
+}
+
+export async function put(key, record) {
+    const db = await open();
+
+    if (db) {
+        return run(db, "readwrite", (store) => store.put(record, key));
+    }
+
+    const records = readLocal();
+    records[key] = record;
+    writeLocal(records);
+}
+
+export async function remove(key) {
+    const db = await open();
+
+    if (db) {
+        return run(db, "readwrite", (store) => store.delete(key));
+    }
+
+    const records = readLocal();
+    delete records[key];
+    writeLocal(records);
+}
+
+export async function getAll() {
+    const db = await open();
+
+    if (db) {
+        return run(db, "readonly", (store) => store.getAll());
+    }
+
+    return Object.values(readLocal());
+}
+"#)]
+extern "C" {
+    fn put(key: &str, record: &str) -> js_sys::Promise;
+
+    fn remove(key: &str) -> js_sys::Promise;
+
+    #[wasm_bindgen(js_name = getAll)]
+    fn get_all() -> js_sys::Promise;
+}
+
+/// Stores `pending` so it survives the page. Writes happen in the
+/// background; a failure only means the item can't be replayed later.
+pub fn save(pending: &Pending) {
+    let uuid = match pending.uuid() {
+        Some(uuid) => uuid,
+        None => return,
+    };
+
+    let record = match serde_json::to_string(pending) {
+        Ok(record) => record,
+        Err(_) => return,
+    };
+
+    spawn(put(uuid, &record));
+}
+
+pub fn forget(uuid: &str) {
+    spawn(remove(uuid));
+}
+
+/// Every stored record, skipping any that no longer parse.
+pub async fn load() -> Vec<Pending> {
+    let records = match JsFuture::from(get_all()).await {
+        Ok(records) => js_sys::Array::from(&records),
+        Err(_) => return vec![],
+    };
+
+    records
+        .iter()
+        .filter_map(|record| record.as_string())
+        .filter_map(|record| serde_json::from_str(&record).ok())
+        .collect()
+}
+
+fn spawn(promise: js_sys::Promise) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let _ = JsFuture::from(promise).await;
+    });
+}
@@ -0,0 +1,149 @@
+use ::{serde_json::Value, std::collections::HashMap};
+
+use crate::types::{Item, Level};
+
+const WINDOW_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    MaxItems(u32),
+    ItemsPerMinute(u32),
+}
+
+impl Limit {
+    /// The synthetic item reported once when this limit is reached.
+    pub fn item(self) -> Item {
+        let (message, key, limit) = match self {
+            Limit::MaxItems(limit) => (
+                "maxItems has been hit. Ignoring errors until reset.",
+                "maxItems",
+                limit,
+            ),
+            Limit::ItemsPerMinute(limit) => (
+                "itemsPerMinute has been hit. Ignoring errors until the next minute.",
+                "itemsPerMinute",
+                limit,
+            ),
+        };
+
+        let mut extra = HashMap::new();
+        extra.insert(key.to_owned(), Value::from(limit));
+
+        Item::from((Level::Warning, message, extra))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Send,
+    /// The item should be sent, but it used up the last slot of a limit.
+    SendAndNotify(Limit),
+    Suppress,
+}
+
+/// Client side limits following rollbar.js semantics: a limit of zero (or
+/// none at all) is unlimited, `max_items` counts every item for the life of
+/// the process and `items_per_minute` resets once a minute has elapsed.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    items_per_minute: u32,
+    max_items: u32,
+    window_start: u64,
+    per_minute_count: u32,
+    count: u32,
+    suppressed: u64,
+}
+
+impl RateLimiter {
+    pub fn new(items_per_minute: Option<u32>, max_items: Option<u32>) -> Self {
+        Self {
+            items_per_minute: items_per_minute.unwrap_or(0),
+            max_items: max_items.unwrap_or(0),
+            window_start: 0,
+            per_minute_count: 0,
+            count: 0,
+            suppressed: 0,
+        }
+    }
+
+    pub fn check(&mut self, now: u64) -> Decision {
+        if now < self.window_start || now - self.window_start >= WINDOW_MS {
+            self.window_start = now;
+            self.per_minute_count = 0;
+        }
+
+        if exceeded(self.max_items, self.count)
+            || exceeded(self.items_per_minute, self.per_minute_count)
+        {
+            self.suppressed = self.suppressed.saturating_add(1);
+            return Decision::Suppress;
+        }
+
+        self.count = self.count.saturating_add(1);
+        self.per_minute_count = self.per_minute_count.saturating_add(1);
+
+        if exceeded(self.max_items, self.count) {
+            Decision::SendAndNotify(Limit::MaxItems(self.max_items))
+        } else if exceeded(self.items_per_minute, self.per_minute_count) {
+            Decision::SendAndNotify(Limit::ItemsPerMinute(self.items_per_minute))
+        } else {
+            Decision::Send
+        }
+    }
+
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+}
+
+fn exceeded(limit: u32, count: u32) -> bool {
+    limit > 0 && count >= limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_by_default() {
+        let mut limiter = RateLimiter::new(None, Some(0));
+
+        for now in 0..1_000 {
+            assert_eq!(limiter.check(now), Decision::Send);
+        }
+
+        assert_eq!(limiter.suppressed(), 0);
+    }
+
+    #[test]
+    fn max_items_notifies_once_then_suppresses() {
+        let mut limiter = RateLimiter::new(None, Some(3));
+
+        assert_eq!(limiter.check(0), Decision::Send);
+        assert_eq!(limiter.check(1), Decision::Send);
+        assert_eq!(
+            limiter.check(2),
+            Decision::SendAndNotify(Limit::MaxItems(3))
+        );
+        assert_eq!(limiter.check(3), Decision::Suppress);
+        assert_eq!(limiter.check(WINDOW_MS * 10), Decision::Suppress);
+
+        assert_eq!(limiter.suppressed(), 2);
+    }
+
+    #[test]
+    fn items_per_minute_resets_each_window() {
+        let mut limiter = RateLimiter::new(Some(2), None);
+
+        assert_eq!(limiter.check(0), Decision::Send);
+        assert_eq!(
+            limiter.check(10),
+            Decision::SendAndNotify(Limit::ItemsPerMinute(2))
+        );
+        assert_eq!(limiter.check(20), Decision::Suppress);
+        assert_eq!(limiter.check(WINDOW_MS - 1), Decision::Suppress);
+
+        assert_eq!(limiter.check(WINDOW_MS), Decision::Send);
+        assert_eq!(limiter.suppressed(), 2);
+    }
+}
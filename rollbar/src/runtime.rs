@@ -6,11 +6,21 @@ pub enum Error {
     NoResponse,
 }
 
-use futures::Future;
+use {futures::Future, std::time::Duration};
 
 #[cfg(not(target_arch = "wasm32"))]
 use {once_cell::sync::OnceCell, tokio::runtime::Runtime};
 
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> JsValue;
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn get_runtime() -> Result<&'static Runtime, Error> {
     static RUNTIME: OnceCell<Runtime> = OnceCell::new();
@@ -43,7 +53,7 @@ pub fn block_on<T>(fut: impl Future<Output = T>) -> Result<T, Error> {
 
             Ok(runtime.block_on(fut))
         } else {
-            use futures::{future::FutureExt, sink::SinkExt, executor::LocalPool};
+            use futures::executor::LocalPool;
 
             let mut pool = LocalPool::new();
 
@@ -51,3 +61,34 @@ pub fn block_on<T>(fut: impl Future<Output = T>) -> Result<T, Error> {
         }
     }
 }
+
+pub fn now_ms() -> u64 {
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            use std::time::{SystemTime, UNIX_EPOCH};
+
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as u64)
+                .unwrap_or_default()
+        } else {
+            js_sys::Date::now() as u64
+        }
+    }
+}
+
+pub async fn sleep(duration: Duration) {
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            tokio::time::sleep(duration).await;
+        } else {
+            let timeout = duration.as_millis().min(i32::MAX as u128) as i32;
+
+            let promise = js_sys::Promise::new(&mut |resolve, _| {
+                set_timeout(&resolve, timeout);
+            });
+
+            let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+        }
+    }
+}
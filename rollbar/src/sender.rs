@@ -0,0 +1,72 @@
+use ::{reqwest::Client as HttpClient, std::fmt};
+
+use crate::transport::Error;
+
+cfg_if::cfg_if! {
+    if #[cfg(not(target_arch = "wasm32"))] {
+        pub type SendFuture<'a> = futures::future::BoxFuture<'a, Result<Response, Error>>;
+    } else {
+        pub type SendFuture<'a> = futures::future::LocalBoxFuture<'a, Result<Response, Error>>;
+    }
+}
+
+/// A `POST` to the Rollbar API, ready to be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedRequest {
+    pub url: String,
+    /// Includes the access token and the content type.
+    pub headers: Vec<(String, String)>,
+    /// The item, serialized as JSON.
+    pub body: Vec<u8>,
+}
+
+/// What the Rollbar API responded with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Delivers requests to the Rollbar API. Errors that mean the API couldn't
+/// be reached at all should be `Error::Http` or `Error::Sender`, so the wasm
+/// build keeps persisted items for the next page load.
+pub trait Sender: Send + Sync {
+    fn send(&self, request: PreparedRequest) -> SendFuture<'_>;
+}
+
+impl fmt::Debug for dyn Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sender")
+    }
+}
+
+/// The default sender.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestSender {
+    client: HttpClient,
+}
+
+impl ReqwestSender {
+    pub fn new(client: HttpClient) -> Self {
+        Self { client }
+    }
+}
+
+impl Sender for ReqwestSender {
+    fn send(&self, request: PreparedRequest) -> SendFuture<'_> {
+        Box::pin(async move {
+            let mut builder = self.client.post(&request.url);
+
+            for (name, value) in &request.headers {
+                builder = builder.header(name, value);
+            }
+
+            let response = builder.body(request.body).send().await?;
+
+            let status = response.status().as_u16();
+            let body = response.text().await.unwrap_or_default();
+
+            Ok(Response { status, body })
+        })
+    }
+}
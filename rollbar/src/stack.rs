@@ -0,0 +1,191 @@
+//! Parsing of JavaScript error stacks, shared by the Node and wasm bindings.
+
+use crate::types::{Exception, Frame, Trace};
+
+/// Errors are followed through at most this many `cause`s, which also guards
+/// against cycles.
+pub const MAX_CAUSES: usize = 10;
+
+/// Follows an error through its `cause`s, most recent first. `read` returns
+/// the trace of an error along with its cause, if any.
+pub fn chain<E, Err>(
+    error: E,
+    mut read: impl FnMut(E) -> Result<(Trace, Option<E>), Err>,
+) -> Result<Vec<Trace>, Err> {
+    let mut traces = vec![];
+    let mut next = Some(error);
+
+    while let Some(error) = next.take() {
+        if traces.len() > MAX_CAUSES {
+            break;
+        }
+
+        let (trace, cause) = read(error)?;
+
+        traces.push(trace);
+        next = cause;
+    }
+
+    Ok(traces)
+}
+
+/// Builds a trace from the `name`, `message` and `stack` of a JS error.
+pub fn trace(name: &str, message: &str, stack: Option<&str>) -> Trace {
+    let message = if message.is_empty() {
+        None
+    } else {
+        Some(message.to_owned())
+    };
+
+    let class = if name.is_empty() { "Error" } else { name };
+
+    Trace::new(
+        stack.map(parse).unwrap_or_default(),
+        Exception::new(class, message),
+    )
+}
+
+/// Parses a V8 `Error.prototype.stack` string into frames, oldest first.
+/// Lines that aren't frames, such as the leading `Name: message`, are
+/// skipped. The `method@location` format of Firefox and Safari is accepted
+/// as well, so browser errors get frames too.
+pub fn parse(stack: &str) -> Vec<Frame> {
+    let mut frames: Vec<Frame> = stack.lines().filter_map(parse_line).collect();
+
+    frames.reverse();
+
+    frames
+}
+
+fn parse_line(line: &str) -> Option<Frame> {
+    let line = line.trim();
+
+    if let Some(frame) = line.strip_prefix("at ") {
+        return Some(parse_v8(frame.trim()));
+    }
+
+    // Messages can contain an `@` too, so only lines that end in a position
+    // are taken as frames.
+    let (method, location) = line.rsplit_once('@')?;
+
+    Some(location_frame(location, method)).filter(|frame| frame.has_position())
+}
+
+/// `method (location)` or a bare `location`. Code run through `eval` has the
+/// location `eval at method (location), <anonymous>:1:1`, of which only the
+/// position inside the evaluated code is kept.
+fn parse_v8(frame: &str) -> Frame {
+    let (method, location) = match frame.strip_suffix(')') {
+        Some(rest) => match rest.find(" (") {
+            Some(idx) => (&rest[..idx], &rest[idx + 2..]),
+            None => ("", frame),
+        },
+        None => ("", frame),
+    };
+
+    let location = match location.rsplit_once(", ") {
+        Some((_, position)) if location.starts_with("eval at ") => position,
+        _ => location,
+    };
+
+    location_frame(location, method)
+}
+
+fn location_frame(location: &str, method: &str) -> Frame {
+    let method = match method.trim() {
+        "" => None,
+        method => Some(method.to_owned()),
+    };
+
+    let mut parts = location.rsplitn(3, ':');
+
+    let colno = parts.next().and_then(|part| part.parse().ok());
+    let lineno = parts.next().and_then(|part| part.parse().ok());
+
+    match (parts.next(), lineno, colno) {
+        (Some(filename), Some(lineno), Some(colno)) => {
+            Frame::new(filename, Some(lineno), Some(colno), method)
+        }
+        _ => Frame::new(location, None, None, method),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_v8_stack() {
+        let stack = "TypeError: boom\n    \
+            at Object.<anonymous> (/app/index.js:10:15)\n    \
+            at new Widget (file:///app/widget.mjs:3:7)\n    \
+            at async Promise.all (index 0)\n    \
+            at node:internal/main/run_main_module:22:47\n    \
+            at eval (eval at run (/app/run.js:2:3), <anonymous>:1:1)";
+
+        let frames = parse(stack);
+
+        assert_eq!(
+            frames,
+            vec![
+                Frame::new("<anonymous>", Some(1), Some(1), Some("eval".into())),
+                Frame::new(
+                    "node:internal/main/run_main_module",
+                    Some(22),
+                    Some(47),
+                    None
+                ),
+                Frame::new("index 0", None, None, Some("async Promise.all".into())),
+                Frame::new(
+                    "file:///app/widget.mjs",
+                    Some(3),
+                    Some(7),
+                    Some("new Widget".into())
+                ),
+                Frame::new(
+                    "/app/index.js",
+                    Some(10),
+                    Some(15),
+                    Some("Object.<anonymous>".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_firefox_stack() {
+        let frames = parse(
+            "mailto@example.com failed\nhandler@https://example.com/app.js:4:9\n@debugger eval code:1:1",
+        );
+
+        assert_eq!(
+            frames,
+            vec![
+                Frame::new("debugger eval code", Some(1), Some(1), None),
+                Frame::new(
+                    "https://example.com/app.js",
+                    Some(4),
+                    Some(9),
+                    Some("handler".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn chain_stops_after_max_causes() {
+        let traces: Result<_, ()> = chain(0, |depth| {
+            Ok((trace("Error", &depth.to_string(), None), Some(depth + 1)))
+        });
+
+        assert_eq!(traces.unwrap().len(), MAX_CAUSES + 1);
+    }
+
+    #[test]
+    fn trace_defaults_class_and_message() {
+        assert_eq!(
+            trace("", "", None),
+            Trace::new(vec![], Exception::new("Error", None))
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use ::{
+    serde::{Deserialize, Serialize},
+    std::collections::VecDeque,
+};
+
+use crate::types::Telemetry;
+
+/// Which browser events the wasm build records as telemetry. Everything is
+/// off unless enabled.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AutoInstrument {
+    /// `console.*` calls.
+    pub log: bool,
+    /// Requests made with `fetch` and `XMLHttpRequest`.
+    pub network: bool,
+    /// `history` changes and `popstate`.
+    pub navigation: bool,
+    /// Clicks anywhere in the document.
+    pub dom: bool,
+}
+
+/// Keeps the most recent `capacity` events, dropping the oldest first.
+#[derive(Debug, Clone)]
+pub struct TelemetryBuffer {
+    capacity: usize,
+    events: VecDeque<Telemetry>,
+}
+
+impl TelemetryBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, event: Telemetry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        while self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(event);
+    }
+
+    /// Empties the buffer, returning its events oldest first.
+    pub fn take(&mut self) -> Vec<Telemetry> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Event, Level};
+
+    fn log(message: &str) -> Telemetry {
+        Telemetry::new(
+            Level::Info,
+            Event::Log {
+                message: message.into(),
+            },
+            0,
+        )
+    }
+
+    #[test]
+    fn keeps_most_recent_events() {
+        let mut buffer = TelemetryBuffer::new(2);
+
+        buffer.push(log("one"));
+        buffer.push(log("two"));
+        buffer.push(log("three"));
+
+        assert_eq!(buffer.take(), vec![log("two"), log("three")]);
+        assert!(buffer.take().is_empty());
+    }
+
+    #[test]
+    fn zero_capacity_records_nothing() {
+        let mut buffer = TelemetryBuffer::new(0);
+
+        buffer.push(log("one"));
+
+        assert!(buffer.take().is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! In-memory stand-ins for Rollbar, so tests can check what was reported
+//! without running an HTTP server.
+
+use std::{
+    collections::VecDeque,
+    ops::Deref,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    runtime,
+    sender::{PreparedRequest, Response, SendFuture, Sender},
+    transport::{Config, Error, Transport},
+    types::{Item, Level},
+};
+
+/// A `Sender` that keeps every item exactly as it would have been posted,
+/// and accepts it unless told to answer otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingSender {
+    items: Arc<Mutex<Vec<Item>>>,
+    responses: Arc<Mutex<VecDeque<Response>>>,
+}
+
+impl RecordingSender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the next request with `status` and `body` instead of
+    /// accepting it. Queued answers are used in order.
+    pub fn respond_with(&self, status: u16, body: impl Into<String>) {
+        let response = Response {
+            status,
+            body: body.into(),
+        };
+
+        lock(&self.responses).push_back(response);
+    }
+
+    /// The items received so far, oldest first.
+    pub fn items(&self) -> Vec<Item> {
+        self.lock().clone()
+    }
+
+    /// Returns the items received so far and forgets them.
+    pub fn take_items(&self) -> Vec<Item> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Panics unless an item at `level` whose message contains
+    /// `message_contains` was received.
+    pub fn assert_reported(&self, level: Level, message_contains: &str) {
+        let items = self.lock();
+
+        let reported = items
+            .iter()
+            .any(|item| item.data.level == level && item.message().contains(message_contains));
+
+        assert!(
+            reported,
+            "no {:?} item containing {:?} was reported; got {:?}",
+            level,
+            message_contains,
+            items
+                .iter()
+                .map(|item| (&item.data.level, item.message()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Item>> {
+        lock(&self.items)
+    }
+}
+
+// A panicking assertion must not hide the items from later ones.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Sender for RecordingSender {
+    fn send(&self, request: PreparedRequest) -> SendFuture<'_> {
+        Box::pin(async move {
+            let item: Item = serde_json::from_slice(&request.body)?;
+
+            let response = lock(&self.responses).pop_front();
+
+            let response = response.unwrap_or_else(|| Response {
+                status: 200,
+                body: serde_json::json!({
+                    "err": 0,
+                    "result": { "id": null, "uuid": item.data.uuid },
+                })
+                .to_string(),
+            });
+
+            self.lock().push(item);
+
+            Ok(response)
+        })
+    }
+}
+
+/// A `Transport` delivering to a `RecordingSender`. Reading the items first
+/// waits for everything queued to be handled.
+///
+/// `items`, `take_items` and `assert_reported` block on the library's own
+/// runtime, which panics inside another runtime, so `#[tokio::test]`s use
+/// the `_async` variants instead. The same goes for `Transport::send`, which
+/// `deliver` replaces there.
+#[derive(Debug, Clone)]
+pub struct RecordingTransport {
+    transport: Transport,
+    sender: RecordingSender,
+}
+
+impl RecordingTransport {
+    pub fn new() -> Result<Self, Error> {
+        Self::with_config(Config::builder().access_token("test").build())
+    }
+
+    pub fn with_config(config: Config) -> Result<Self, Error> {
+        let sender = RecordingSender::new();
+        let transport = Transport::with_sender(config, sender.clone())?;
+
+        Ok(Self { transport, sender })
+    }
+
+    pub fn sender(&self) -> &RecordingSender {
+        &self.sender
+    }
+
+    pub fn items(&self) -> Result<Vec<Item>, Error> {
+        self.flush_blocking()?;
+
+        Ok(self.sender.items())
+    }
+
+    pub async fn items_async(&self) -> Result<Vec<Item>, Error> {
+        self.transport.flush().await?;
+
+        Ok(self.sender.items())
+    }
+
+    pub fn take_items(&self) -> Result<Vec<Item>, Error> {
+        self.flush_blocking()?;
+
+        Ok(self.sender.take_items())
+    }
+
+    pub async fn take_items_async(&self) -> Result<Vec<Item>, Error> {
+        self.transport.flush().await?;
+
+        Ok(self.sender.take_items())
+    }
+
+    /// See `RecordingSender::assert_reported`.
+    pub fn assert_reported(&self, level: Level, message_contains: &str) {
+        self.flush_blocking()
+            .expect("failed to flush the transport");
+
+        self.sender.assert_reported(level, message_contains);
+    }
+
+    pub async fn assert_reported_async(&self, level: Level, message_contains: &str) {
+        self.transport
+            .flush()
+            .await
+            .expect("failed to flush the transport");
+
+        self.sender.assert_reported(level, message_contains);
+    }
+
+    fn flush_blocking(&self) -> Result<(), Error> {
+        runtime::block_on(self.transport.flush())?
+    }
+}
+
+impl Deref for RecordingTransport {
+    type Target = Transport;
+
+    fn deref(&self) -> &Transport {
+        &self.transport
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_prepared_items() {
+        let transport = RecordingTransport::with_config(
+            Config::builder()
+                .access_token("test")
+                .ignored_messages(vec!["^noise".to_owned()])
+                .build(),
+        )
+        .unwrap();
+
+        for (level, message) in [(Level::Error, "boom"), (Level::Info, "noise")] {
+            transport
+                .send(Item::from((level, message, Default::default())))
+                .unwrap();
+        }
+
+        transport.assert_reported(Level::Error, "boo");
+
+        let items = transport.take_items().unwrap();
+        assert_eq!(items.len(), 1);
+        assert!(items[0].data.uuid.is_some());
+        assert!(transport.items().unwrap().is_empty());
+
+        transport.shutdown().unwrap();
+    }
+
+    #[tokio::test]
+    async fn records_items_inside_a_runtime() {
+        let transport = RecordingTransport::new().unwrap();
+
+        let mut item = Item::from((Level::Error, "boom", Default::default()));
+        transport.prepare(&mut item).unwrap();
+        transport.deliver(item).await.unwrap();
+
+        transport.assert_reported_async(Level::Error, "boom").await;
+
+        assert_eq!(transport.take_items_async().await.unwrap().len(), 1);
+        assert!(transport.items_async().await.unwrap().is_empty());
+
+        transport.close().await.unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "no Warning item")]
+    fn assert_reported_panics_when_missing() {
+        let transport = RecordingTransport::new().unwrap();
+
+        transport
+            .send(Item::from((Level::Error, "boom", Default::default())))
+            .unwrap();
+
+        transport.assert_reported(Level::Warning, "boom");
+    }
+}
@@ -6,6 +6,22 @@ pub enum Error {
     MessagesLock,
     #[error("QueueDepthLock: could not obtain lock on queue depth.")]
     QueueDepthLock,
+    #[error("RateLimiterLock: could not obtain lock on rate limiter.")]
+    RateLimiterLock,
+    #[error("DedupLock: could not obtain lock on duplicate tracker.")]
+    DedupLock,
+    #[error("HooksLock: could not obtain lock on hooks.")]
+    HooksLock,
+    #[error("IgnoredMessages: {0}")]
+    IgnoredMessages(#[from] regex::Error),
+    #[error("TelemetryLock: could not obtain lock on telemetry buffer.")]
+    TelemetryLock,
+    #[error("PendingLock: could not obtain lock on pending items.")]
+    PendingLock,
+    #[error("PayloadLock: could not obtain lock on payload defaults.")]
+    PayloadLock,
+    #[error("Payload: {0}")]
+    Payload(#[from] serde_json::Error),
     #[error("MaxQueueDepthExceeded")]
     MaxQueueDepthExceeded,
     #[error("QueueDepthOutOfSync")]
@@ -18,46 +34,182 @@ pub enum Error {
     Runtime(#[from] crate::runtime::Error),
     #[error("Http: {0}")]
     Http(#[from] reqwest::Error),
-    #[error("AccessDenied")]
-    AccessDenied,
-    #[error("RateLimited")]
-    RateLimited,
-    #[error("PayloadTooLarge")]
-    PayloadTooLarge,
-    #[error("MissingInfo: the api did not receive enough information for this item.")]
-    MissingInfo,
+    #[error("Sender: {0}")]
+    Sender(Box<dyn std::error::Error + Send + Sync>),
+    #[error("AccessDenied: {0}")]
+    AccessDenied(Rejection),
+    #[error("RateLimited: {0}")]
+    RateLimited(Rejection),
+    #[error("PayloadTooLarge: {0}")]
+    PayloadTooLarge(Rejection),
+    #[error("MissingInfo: the api did not receive enough information for this item. {0}")]
+    MissingInfo(Rejection),
+    #[error("Rejected: {0}")]
+    Rejected(Rejection),
+    #[error("HistoryLock: could not obtain lock on error history.")]
+    HistoryLock,
+    #[error("MetricsLock: could not obtain lock on metrics.")]
+    MetricsLock,
+    #[cfg(feature = "metrics")]
+    #[error("Prometheus: {0}")]
+    Prometheus(#[from] prometheus::Error),
     #[error("Shutdown with errors:\n {0}")]
     Shutdown(String),
+    #[error("Canceled: the item was dropped before it was delivered.")]
+    Canceled,
+    #[error("Timeout: the item was not delivered in time.")]
+    Timeout,
+}
+
+impl Error {
+    /// A stable name for the kind of error, used by the bindings to surface
+    /// typed errors.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::RuntimeCreation(_) => "RuntimeCreation",
+            Error::MessagesLock => "MessagesLock",
+            Error::QueueDepthLock => "QueueDepthLock",
+            Error::RateLimiterLock => "RateLimiterLock",
+            Error::DedupLock => "DedupLock",
+            Error::HooksLock => "HooksLock",
+            Error::IgnoredMessages(_) => "IgnoredMessages",
+            Error::TelemetryLock => "TelemetryLock",
+            Error::PendingLock => "PendingLock",
+            Error::PayloadLock => "PayloadLock",
+            Error::Payload(_) => "Payload",
+            Error::MaxQueueDepthExceeded => "MaxQueueDepthExceeded",
+            Error::QueueDepthOutOfSync => "QueueDepthOutOfSync",
+            Error::TrySend(_) => "TrySend",
+            Error::Send(_) => "Send",
+            Error::Runtime(_) => "Runtime",
+            Error::Http(_) => "Http",
+            Error::Sender(_) => "Sender",
+            Error::AccessDenied(_) => "AccessDenied",
+            Error::RateLimited(_) => "RateLimited",
+            Error::PayloadTooLarge(_) => "PayloadTooLarge",
+            Error::MissingInfo(_) => "MissingInfo",
+            Error::Rejected(_) => "Rejected",
+            Error::HistoryLock => "HistoryLock",
+            Error::MetricsLock => "MetricsLock",
+            #[cfg(feature = "metrics")]
+            Error::Prometheus(_) => "Prometheus",
+            Error::Shutdown(_) => "Shutdown",
+            Error::Canceled => "Canceled",
+            Error::Timeout => "Timeout",
+        }
+    }
+
+    /// What Rollbar responded with, if it refused the item.
+    pub fn rejection(&self) -> Option<&Rejection> {
+        match self {
+            Error::AccessDenied(rejection)
+            | Error::RateLimited(rejection)
+            | Error::PayloadTooLarge(rejection)
+            | Error::MissingInfo(rejection)
+            | Error::Rejected(rejection) => Some(rejection),
+            _ => None,
+        }
+    }
+}
+
+/// The HTTP status and response body of an item Rollbar refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub status: u16,
+    pub body: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status, self.body)
+    }
 }
 
 use ::{
-    futures::{channel::mpsc, sink::SinkExt, stream::StreamExt},
-    reqwest::Client as HttpClient,
+    futures::{
+        channel::{mpsc, oneshot},
+        future::{self, Either},
+        sink::SinkExt,
+        stream::StreamExt,
+    },
+    regex::RegexSet,
     reqwest::StatusCode,
     serde::{Deserialize, Serialize},
-    std::sync::{Arc, Mutex},
+    serde_json::Value,
+    std::{
+        fmt,
+        sync::{Arc, Mutex, RwLock},
+        time::Duration,
+    },
 };
 
-use crate::{runtime, types::Item};
+use crate::{
+    dedup::{Deduplicator, Observation},
+    history::{Failure, History, Stats},
+    hooks::{Delivery, Hooks},
+    metrics::{self, Family, Metrics, Policy},
+    pending::Pending,
+    rate_limiter::{Decision, RateLimiter},
+    runtime,
+    sender::{PreparedRequest, ReqwestSender, Response, Sender},
+    telemetry::{AutoInstrument, TelemetryBuffer},
+    types::{Item, Level, Telemetry},
+};
+
+use uuid::Uuid;
+
+#[cfg(target_arch = "wasm32")]
+use crate::{pending, persist};
 
 const QUEUE_DEPTH: usize = 50;
-const API_ENDPOINT: &'static str = "api/1/item";
+const API_ENDPOINT: &str = "api/1/item";
+const DRAIN_POLL_MS: u64 = 5;
 
 #[derive(Debug, Clone)]
 pub struct Transport {
     messages: Arc<Mutex<mpsc::Sender<Message>>>,
     queue_depth: Arc<Mutex<u64>>,
-    errors: Arc<Mutex<Vec<Error>>>,
-    client: HttpClient,
+    history: Arc<Mutex<History>>,
+    metrics: Arc<Mutex<Metrics>>,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    dedup: Arc<Mutex<Deduplicator>>,
+    ignored_messages: RegexSet,
+    hooks: Arc<RwLock<Hooks>>,
+    payload: Arc<RwLock<Value>>,
+    telemetry: Arc<Mutex<TelemetryBuffer>>,
+    /// Items queued but not yet delivered, oldest first.
+    pending: Arc<Mutex<Vec<Pending>>>,
+    sender: Arc<dyn Sender>,
     config: Config,
 }
 
-#[derive(Debug, PartialEq)]
+/// Sent the uuid of the occurrence once Rollbar accepts the item.
+type Receipt = oneshot::Sender<Result<String, Error>>;
+
+#[derive(Debug)]
 pub enum Message {
-    Item(Item),
+    Item(Box<Item>, Option<Receipt>),
     Shutdown,
 }
 
+/// An item with the access token in the body rather than a header.
+#[derive(Debug, Serialize)]
+struct TokenItem<'a> {
+    access_token: &'a str,
+    #[serde(flatten)]
+    item: &'a Item,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    result: Option<ApiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResult {
+    uuid: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, typed_builder::TypedBuilder)]
 #[serde(rename_all = "camelCase")]
 #[cfg_attr(feature = "c", repr(C))]
@@ -71,23 +223,126 @@ pub struct Config {
     pub code_version: Option<String>,
     #[builder(setter(into), default = Option::None)]
     pub environment: Option<String>,
+    /// Maximum number of items sent per minute. `None` or `0` is unlimited.
+    #[builder(setter(into), default = Option::None)]
+    pub items_per_minute: Option<u32>,
+    /// Maximum number of items sent for the life of the process. `None` or
+    /// `0` is unlimited.
+    #[builder(setter(into), default = Option::None)]
+    pub max_items: Option<u32>,
+    /// Repeats of an item seen within this many milliseconds of the first
+    /// occurrence are counted instead of sent. `None` or `0` disables it.
+    #[builder(setter(into), default = Option::None)]
+    pub dedup_window_ms: Option<u64>,
+    /// Items whose message matches any of these regular expressions are not
+    /// sent.
+    #[builder(setter(into), default)]
+    #[serde(default)]
+    pub ignored_messages: Vec<String>,
+    /// Defaults deep-merged into the `data` of every item. Values already
+    /// set on an item take precedence.
+    #[builder(setter(into), default)]
+    #[serde(default)]
+    pub payload: Value,
+    /// Have the bindings report uncaught exceptions at `critical` level.
+    #[builder(default)]
+    #[serde(default)]
+    pub capture_uncaught: bool,
+    /// Have the bindings report unhandled promise rejections at `critical`
+    /// level.
+    #[builder(default)]
+    #[serde(default)]
+    pub capture_unhandled_rejections: bool,
+    /// Browser events the wasm build records as telemetry.
+    #[builder(default)]
+    #[serde(default)]
+    pub auto_instrument: AutoInstrument,
+    /// How many telemetry events are kept for the next error.
+    #[builder(default = Config::default_max_telemetry_events())]
+    #[serde(default = "Config::default_max_telemetry_events")]
+    pub max_telemetry_events: usize,
+    /// Keep undelivered items in IndexedDB (or localStorage) so the wasm
+    /// build can send them on the next page load.
+    #[builder(default)]
+    #[serde(default)]
+    pub persist_queue: bool,
+    /// Persisted items older than this are discarded instead of sent.
+    #[builder(default = Config::default_max_persisted_age_ms())]
+    #[serde(default = "Config::default_max_persisted_age_ms")]
+    pub max_persisted_age_ms: u64,
+    /// At most this many persisted items, the most recent, are sent.
+    #[builder(default = Config::default_max_persisted_items())]
+    #[serde(default = "Config::default_max_persisted_items")]
+    pub max_persisted_items: usize,
+    /// How many delivery errors `recent_errors` keeps.
+    #[builder(default = Config::default_max_recent_errors())]
+    #[serde(default = "Config::default_max_recent_errors")]
+    pub max_recent_errors: usize,
+    /// Reported as `client.javascript.source_map_enabled`, telling Rollbar
+    /// to de-minify browser stack traces with uploaded source maps.
+    #[builder(default)]
+    #[serde(default)]
+    pub source_map_enabled: bool,
+    /// Reported as `client.javascript.guess_uncaught_frames`, letting
+    /// Rollbar guess frames for uncaught errors that lack a stack.
+    #[builder(default)]
+    #[serde(default)]
+    pub guess_uncaught_frames: bool,
 }
 
 impl Config {
     pub fn default_uri() -> String {
         "https://api.rollbar.com".into()
     }
+
+    pub fn default_max_telemetry_events() -> usize {
+        100
+    }
+
+    pub fn default_max_persisted_age_ms() -> u64 {
+        24 * 60 * 60 * 1000
+    }
+
+    pub fn default_max_persisted_items() -> usize {
+        50
+    }
+
+    pub fn default_max_recent_errors() -> usize {
+        20
+    }
 }
 
 impl Transport {
     pub fn new(config: Config) -> Result<Self, Error> {
-        let (messages, rcv_messages) = mpsc::channel(QUEUE_DEPTH as usize);
+        Self::with_sender(config, ReqwestSender::default())
+    }
+
+    /// Creates a transport that delivers items with `sender` rather than
+    /// `reqwest`.
+    pub fn with_sender(config: Config, sender: impl Sender + 'static) -> Result<Self, Error> {
+        let (messages, rcv_messages) = mpsc::channel(QUEUE_DEPTH);
+
+        let rate_limiter = RateLimiter::new(config.items_per_minute, config.max_items);
+
+        let dedup = Deduplicator::new(config.dedup_window_ms);
+
+        let ignored_messages = RegexSet::new(&config.ignored_messages)?;
 
         let this = Self {
             messages: Arc::new(Mutex::new(messages)),
             queue_depth: Arc::new(Mutex::new(0)),
-            errors: Arc::new(Mutex::new(vec![])),
-            client: HttpClient::new(),
+            history: Arc::new(Mutex::new(History::new(config.max_recent_errors))),
+            metrics: Arc::new(Mutex::new(Metrics::default())),
+            rate_limiter: Arc::new(Mutex::new(rate_limiter)),
+            dedup: Arc::new(Mutex::new(dedup)),
+            ignored_messages,
+            hooks: Arc::new(RwLock::new(Hooks::default())),
+            payload: Arc::new(RwLock::new(config.payload.clone())),
+            telemetry: Arc::new(Mutex::new(TelemetryBuffer::new(
+                config.max_telemetry_events,
+            ))),
+            pending: Arc::new(Mutex::new(vec![])),
+            sender: Arc::new(sender),
             config,
         };
 
@@ -96,8 +351,12 @@ impl Transport {
         Ok(this)
     }
 
-    fn send_message(&self, message: Message) -> Result<(), Error> {
-        if message != Message::Shutdown {
+    async fn send_message(&self, message: Message) -> Result<(), Error> {
+        let counted = !matches!(message, Message::Shutdown);
+
+        // Counted before sending, so the worker never handles an item it
+        // doesn't know about yet.
+        if counted {
             let mut queue_depth = self.queue_depth.lock().map_err(|_| Error::QueueDepthLock)?;
 
             *queue_depth = queue_depth
@@ -105,60 +364,547 @@ impl Transport {
                 .ok_or(Error::MaxQueueDepthExceeded)?;
         }
 
-        let sent = runtime::block_on(async move {
-            let mut messages = self.messages.lock().map_err(|_| Error::MessagesLock)?;
+        let mut messages = self
+            .messages
+            .lock()
+            .map_err(|_| Error::MessagesLock)?
+            .clone();
 
-            messages.send(message).await?;
+        if let Err(error) = messages.send(message).await {
+            // The worker will never see the item, e.g. after `close`.
+            if counted {
+                let mut queue_depth = self.queue_depth.lock().map_err(|_| Error::QueueDepthLock)?;
 
-            Ok(())
-        })?;
+                *queue_depth = queue_depth.saturating_sub(1);
+            }
+
+            return Err(error.into());
+        }
+
+        Ok(())
+    }
+
+    /// Applies the rate limits and queues the item, returning `false` if it
+    /// was suppressed.
+    async fn enqueue(&self, item: Item, receipt: Option<Receipt>) -> Result<bool, Error> {
+        let now = runtime::now_ms();
+
+        self.enqueue_pending(Pending::new(item, now), receipt).await
+    }
+
+    async fn enqueue_pending(
+        &self,
+        pending: Pending,
+        receipt: Option<Receipt>,
+    ) -> Result<bool, Error> {
+        let decision = self
+            .rate_limiter
+            .lock()
+            .map_err(|_| Error::RateLimiterLock)?
+            .check(runtime::now_ms());
+
+        match decision {
+            Decision::Send => {
+                self.queue(pending, receipt).await?;
+            }
+            Decision::SendAndNotify(limit) => {
+                let mut notice = limit.item();
+
+                self.prepare(&mut notice)?;
+
+                self.queue(pending, receipt).await?;
+                self.queue(Pending::new(notice, runtime::now_ms()), None)
+                    .await?;
+            }
+            Decision::Suppress => {
+                self.count_dropped(Policy::RateLimit);
+
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn queue(&self, pending: Pending, receipt: Option<Receipt>) -> Result<(), Error> {
+        let uuid = pending.uuid().map(str::to_owned);
+
+        // Tracked before sending, since the worker skips items it can't find
+        // in `pending`.
+        if uuid.is_some() {
+            self.pending
+                .lock()
+                .map_err(|_| Error::PendingLock)?
+                .push(pending.clone());
+        }
+
+        let sent = self
+            .send_message(Message::Item(Box::new(pending.item.clone()), receipt))
+            .await;
+
+        if let Some(uuid) = &uuid {
+            let mut tracked = self.pending.lock().map_err(|_| Error::PendingLock)?;
+
+            if sent.is_ok() {
+                // Persisted only once the worker has the item, and only while
+                // it's pending, so `release` hasn't forgotten it already.
+                if tracked.iter().any(|tracked| tracked.uuid() == Some(uuid)) {
+                    self.persist(&pending);
+                }
+            } else {
+                // The caller gets the error, so neither `take_pending` nor the
+                // next page may send the item anyway.
+                tracked.retain(|tracked| tracked.uuid() != Some(uuid));
+            }
+        }
+
+        if sent.is_ok() {
+            if let Ok(mut metrics) = self.metrics.lock() {
+                metrics.enqueued();
+            }
+        }
 
         sent
     }
 
-    pub fn send(&self, item: Item) -> Result<(), Error> {
-        self.send_message(Message::Item(item))
+    /// Takes every item that has been queued but not yet delivered, along
+    /// with the summaries of open duplicate windows, so they can be sent some
+    /// other way, e.g. with `navigator.sendBeacon` as a page unloads. Taken
+    /// items still waiting in the queue are skipped when their turn comes.
+    /// One may already be on its way; Rollbar discards the second copy since
+    /// both share a uuid.
+    pub fn take_pending(&self) -> Result<Vec<Item>, Error> {
+        let summaries = self.dedup.lock().map_err(|_| Error::DedupLock)?.take_all();
+
+        let pending: Vec<Pending> = self
+            .pending
+            .lock()
+            .map_err(|_| Error::PendingLock)?
+            .drain(..)
+            .collect();
+
+        let mut items = Vec::with_capacity(pending.len() + summaries.len());
+
+        for pending in pending {
+            if let Some(uuid) = pending.uuid() {
+                self.unpersist(uuid);
+            }
+
+            items.push(pending.item);
+        }
+
+        items.extend(summaries);
+
+        Ok(items)
+    }
+
+    /// Whether `item` still needs to be delivered by the worker, rather than
+    /// having been taken by `take_pending`.
+    fn is_pending(&self, item: &Item) -> Result<bool, Error> {
+        let uuid = match &item.data.uuid {
+            Some(uuid) => uuid.as_str(),
+            None => return Ok(true),
+        };
+
+        let pending = self.pending.lock().map_err(|_| Error::PendingLock)?;
+
+        Ok(pending.iter().any(|pending| pending.uuid() == Some(uuid)))
     }
 
-    pub fn shutdown(&self) -> Result<(), Error> {
-        self.send_message(Message::Shutdown)?;
+    /// Forgets an item the worker is done with. Items that couldn't reach
+    /// Rollbar at all stay persisted, to be sent on the next page load.
+    fn release(&self, item: &Item, result: &Result<String, Error>) -> Result<(), Error> {
+        let uuid = match &item.data.uuid {
+            Some(uuid) => uuid.as_str(),
+            None => return Ok(()),
+        };
+
+        self.pending
+            .lock()
+            .map_err(|_| Error::PendingLock)?
+            .retain(|pending| pending.uuid() != Some(uuid));
+
+        if !matches!(result, Err(Error::Http(_) | Error::Sender(_))) {
+            self.unpersist(uuid);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn persist(&self, pending: &Pending) {
+        if self.config.persist_queue {
+            persist::save(pending);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn persist(&self, _: &Pending) {}
+
+    #[cfg(target_arch = "wasm32")]
+    fn unpersist(&self, uuid: &str) {
+        if self.config.persist_queue {
+            persist::forget(uuid);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn unpersist(&self, _: &str) {}
+
+    /// Queues the items persisted by an earlier page that are still within
+    /// `max_persisted_age_ms`, up to `max_persisted_items` of them. The rest
+    /// are discarded. Returns how many were queued.
+    #[cfg(target_arch = "wasm32")]
+    pub async fn restore(&self) -> Result<usize, Error> {
+        let records = persist::load().await;
+
+        for record in &records {
+            if let Some(uuid) = record.uuid() {
+                persist::forget(uuid);
+            }
+        }
+
+        let records = pending::replayable(
+            records,
+            runtime::now_ms(),
+            self.config.max_persisted_age_ms,
+            self.config.max_persisted_items,
+        );
+
+        let mut restored = 0;
+
+        for record in records {
+            if self.enqueue_pending(record, None).await? {
+                restored += 1;
+
+                if let Ok(mut history) = self.history.lock() {
+                    history.retried();
+                }
+            }
+        }
+
+        Ok(restored)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The url items are posted to.
+    pub fn endpoint(&self) -> String {
+        format!("{}/{}", &self.config.uri, API_ENDPOINT)
+    }
+
+    /// Serializes `item` with the access token in the body, for senders such
+    /// as `navigator.sendBeacon` that can't set headers.
+    pub fn body_with_token(&self, item: &Item) -> Result<String, Error> {
+        let body = TokenItem {
+            access_token: &self.config.access_token,
+            item,
+        };
+
+        Ok(serde_json::to_string(&body)?)
+    }
+
+    /// Prepares, filters and queues an item for delivery.
+    pub fn send(&self, mut item: Item) -> Result<(), Error> {
+        self.prepare(&mut item)?;
+
+        if self.is_ignored(&item)? {
+            return Ok(());
+        }
+
+        self.submit(item)
+    }
+
+    /// Queues an item that has already been through `prepare` and
+    /// `is_ignored`. Bindings use this to run their own hooks in between.
+    pub fn submit(&self, item: Item) -> Result<(), Error> {
+        // Without a receipt, a failure is kept for `shutdown` even if the
+        // worker gets to the item before this returns.
+        runtime::block_on(self.dispatch(item, None))?.map(drop)
+    }
+
+    /// Queues a prepared item like `submit`, then waits for Rollbar to accept
+    /// it. Resolves with the occurrence uuid, or `None` if the item was folded
+    /// into a duplicate or suppressed by a rate limit.
+    pub async fn deliver(&self, item: Item) -> Result<Option<String>, Error> {
+        let (sender, receipt) = oneshot::channel();
+
+        if self.dispatch(item, Some(sender)).await? {
+            receipt.await.map_err(|_| Error::Canceled)?.map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Like `deliver`, but gives up waiting after `timeout`. Items queued
+    /// before this one are sent first, so this also flushes the queue.
+    pub async fn deliver_within(
+        &self,
+        item: Item,
+        timeout: Duration,
+    ) -> Result<Option<String>, Error> {
+        let delivery = Box::pin(self.deliver(item));
+        let timeout = Box::pin(runtime::sleep(timeout));
+
+        match future::select(delivery, timeout).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(Error::Timeout),
+        }
+    }
+
+    /// Returns `false` if the item was folded into a duplicate or suppressed.
+    async fn dispatch(&self, mut item: Item, receipt: Option<Receipt>) -> Result<bool, Error> {
+        let observation = self
+            .dedup
+            .lock()
+            .map_err(|_| Error::DedupLock)?
+            .observe(&item, runtime::now_ms());
+
+        let summary = match observation {
+            Observation::First(summary) => summary,
+            Observation::Folded => {
+                self.count_dropped(Policy::Duplicate);
+
+                return Ok(false);
+            }
+        };
+
+        self.schedule_duplicate_flush()?;
+
+        if let Some(summary) = summary {
+            self.enqueue(*summary, None).await?;
+        }
+
+        self.attach_telemetry(&mut item)?;
+
+        self.enqueue(item, receipt).await
+    }
+
+    /// Moves the buffered telemetry into `item` if it's an error. This
+    /// happens only once the item is known to be sent, so ignored and
+    /// duplicate errors leave it for the next one.
+    fn attach_telemetry(&self, item: &mut Item) -> Result<(), Error> {
+        if matches!(item.data.level, Level::Error | Level::Critical) {
+            let events = self
+                .telemetry
+                .lock()
+                .map_err(|_| Error::TelemetryLock)?
+                .take();
+
+            item.data.body.telemetry.splice(0..0, events);
+        }
+
+        Ok(())
+    }
+
+    fn schedule_duplicate_flush(&self) -> Result<(), Error> {
+        let window_ms = match self.config.dedup_window_ms {
+            Some(window_ms) if window_ms > 0 => window_ms,
+            _ => return Ok(()),
+        };
+
+        let this = self.clone();
+
+        runtime::spawn(async move {
+            runtime::sleep(Duration::from_millis(window_ms)).await;
+
+            if let Err(error) = this.flush_duplicates(false).await {
+                if let Ok(mut history) = this.history.lock() {
+                    history.failed(Failure::new(&error, None, runtime::now_ms()), false);
+                }
+            }
+        })?;
+
+        Ok(())
+    }
 
-        while let Ok(queue_depth) = self.queue_depth.lock() {
-            if *queue_depth == 0 {
-                break;
+    /// Sends the summaries of closed duplicate windows, or of every open
+    /// window when `all` is set.
+    async fn flush_duplicates(&self, all: bool) -> Result<(), Error> {
+        let summaries = {
+            let mut dedup = self.dedup.lock().map_err(|_| Error::DedupLock)?;
+
+            if all {
+                dedup.take_all()
+            } else {
+                dedup.take_expired(runtime::now_ms())
             }
+        };
+
+        for summary in summaries {
+            self.enqueue(summary, None).await?;
+        }
+
+        Ok(())
+    }
+
+    /// The number of items dropped by `items_per_minute` and `max_items`.
+    pub fn suppressed_count(&self) -> Result<u64, Error> {
+        let rate_limiter = self
+            .rate_limiter
+            .lock()
+            .map_err(|_| Error::RateLimiterLock)?;
+
+        Ok(rate_limiter.suppressed())
+    }
+
+    /// Counts an item a binding's own `check_ignore` callback ignored, as
+    /// `is_ignored` does for the predicates it runs.
+    pub fn count_ignored(&self) {
+        self.count_dropped(Policy::Ignored);
+    }
+
+    fn count_dropped(&self, policy: Policy) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.dropped(policy);
         }
+    }
+
+    pub(crate) fn metric_families(&self) -> Result<Vec<Family>, Error> {
+        let queue_depth = *self.queue_depth.lock().map_err(|_| Error::QueueDepthLock)?;
+
+        let metrics = self.metrics.lock().map_err(|_| Error::MetricsLock)?;
+
+        Ok(metrics.families(queue_depth))
+    }
+
+    /// Renders the transport's health counters and histograms in the
+    /// OpenMetrics text format.
+    pub fn metrics_text(&self) -> Result<String, Error> {
+        Ok(metrics::render(&self.metric_families()?))
+    }
+
+    /// Registers the transport's metrics with `registry`.
+    #[cfg(feature = "metrics")]
+    pub fn register_metrics(&self, registry: &prometheus::Registry) -> Result<(), Error> {
+        let collector = metrics::Collector::new(self.clone())?;
+
+        registry.register(Box::new(collector))?;
+
+        Ok(())
+    }
+
+    /// What has become of items so far, along with the latest error.
+    pub fn stats(&self) -> Result<Stats, Error> {
+        let mut stats = Stats {
+            dropped: self.suppressed_count()?,
+            queue_depth: *self.queue_depth.lock().map_err(|_| Error::QueueDepthLock)?,
+            ..Stats::default()
+        };
+
+        self.history
+            .lock()
+            .map_err(|_| Error::HistoryLock)?
+            .stats(&mut stats);
+
+        Ok(stats)
+    }
+
+    /// The most recent delivery errors, oldest first, up to
+    /// `max_recent_errors` of them.
+    pub fn recent_errors(&self) -> Result<Vec<Failure>, Error> {
+        Ok(self
+            .history
+            .lock()
+            .map_err(|_| Error::HistoryLock)?
+            .recent())
+    }
+
+    /// Waits until every queued item, including the summaries of open
+    /// duplicate windows, has been handled by the worker.
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.flush_duplicates(true).await?;
+
+        self.drained().await
+    }
+
+    /// Flushes the queue and stops the worker. Errors from items nobody was
+    /// waiting on are returned together.
+    pub async fn close(&self) -> Result<(), Error> {
+        self.flush_duplicates(true).await?;
+
+        self.send_message(Message::Shutdown).await?;
+
+        self.drained().await?;
 
-        match self.errors.lock() {
-            Ok(errors) if errors.len() > 0 => {
+        let history = match self.history.lock() {
+            Ok(history) => history,
+            Err(_) => return Ok(()),
+        };
+
+        match history.unclaimed() {
+            (failures, 0) if failures.is_empty() => Ok(()),
+            (failures, overflow) => {
                 let mut batch = String::new();
 
-                for error in &errors[..] {
-                    batch.push_str(&format!("{}\n", error));
+                for failure in failures {
+                    batch.push_str(&format!("{}\n", failure.message));
+                }
+
+                if overflow > 0 {
+                    batch.push_str(&format!("and {} earlier errors\n", overflow));
                 }
 
                 Err(Error::Shutdown(batch))
             }
-            _ => Ok(()),
         }
     }
 
+    pub fn shutdown(&self) -> Result<(), Error> {
+        runtime::block_on(self.close())?
+    }
+
+    async fn drained(&self) -> Result<(), Error> {
+        while *self.queue_depth.lock().map_err(|_| Error::QueueDepthLock)? > 0 {
+            runtime::sleep(Duration::from_millis(DRAIN_POLL_MS)).await;
+        }
+
+        Ok(())
+    }
+
     fn run(&self, mut messages: mpsc::Receiver<Message>) -> Result<(), Error> {
         let this = self.clone();
 
         let fut = async move {
             while let Some(message) = messages.next().await {
                 match message {
-                    Message::Item(mut item) => {
-                        this.prepare_item(&mut item);
+                    Message::Item(item, receipt) => {
+                        let (result, attempted) = match this.is_pending(&item) {
+                            Ok(true) => {
+                                let result = this.transport(&item).await;
 
-                        if let Err(error) = this.transport(item).await {
-                            let mut errors = match this.errors.lock() {
-                                Ok(errors) => errors,
-                                _ => continue,
-                            };
+                                (this.release(&item, &result).and(result), true)
+                            }
+                            Ok(false) => (Ok(item.data.uuid.clone().unwrap_or_default()), false),
+                            Err(error) => (Err(error), true),
+                        };
+
+                        let failure = result.as_ref().err().map(|error| {
+                            Failure::new(error, item.data.uuid.clone(), runtime::now_ms())
+                        });
+
+                        // Errors nobody is waiting on are kept for `shutdown`.
+                        let claimed = match receipt {
+                            Some(receipt) => receipt.send(result).is_ok(),
+                            None => false,
+                        };
+
+                        if let Ok(mut metrics) = this.metrics.lock() {
+                            match &failure {
+                                Some(failure) => metrics.failed(failure.code),
+                                None if attempted => metrics.sent(),
+                                None => {}
+                            }
+                        }
 
-                            errors.push(error);
+                        if let Ok(mut history) = this.history.lock() {
+                            match failure {
+                                Some(failure) => history.failed(failure, claimed),
+                                None if attempted => history.sent(),
+                                None => {}
+                            }
                         }
 
                         let mut queue_depth =
@@ -191,7 +937,26 @@ impl Transport {
         Ok(())
     }
 
-    fn prepare_item(&self, item: &mut Item) {
+    /// Fills in configured defaults, then runs every registered transform in
+    /// the order they were added.
+    pub fn prepare(&self, item: &mut Item) -> Result<(), Error> {
+        self.prepare_item(item)?;
+
+        let transforms = self
+            .hooks
+            .read()
+            .map_err(|_| Error::HooksLock)?
+            .transforms
+            .clone();
+
+        for transform in transforms {
+            transform(item);
+        }
+
+        Ok(())
+    }
+
+    fn prepare_item(&self, item: &mut Item) -> Result<(), Error> {
         if let Some(environment) = &self.config.environment {
             item.data.environment = Some(environment.clone());
         }
@@ -199,57 +964,482 @@ impl Transport {
         if let Some(code_version) = &self.config.code_version {
             item.data.code_version = Some(code_version.clone());
         }
-    }
 
-    async fn transport(&self, item: Item) -> Result<(), Error> {
-        let result = self
-            .client
-            .post(&format!("{}/{}", &self.config.uri, API_ENDPOINT))
-            .header("X-Rollbar-Access-Token", &self.config.access_token)
-            .json(&item)
-            .send()
-            .await?;
+        item.data
+            .uuid
+            .get_or_insert_with(|| Uuid::new_v4().to_string());
 
-        match result.status() {
-            // TODO: truncate payload... but truncate what?
-            StatusCode::PAYLOAD_TOO_LARGE => Err(Error::PayloadTooLarge),
-            // TODO: wait for x to retry.
-            StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited),
-            // TODO: don't send requests until reconfigured with new token?
-            StatusCode::FORBIDDEN => Err(Error::AccessDenied),
-            // TODO: possibly unnecessary if library guaranteed to not send invalid requests.
-            StatusCode::UNPROCESSABLE_ENTITY => Err(Error::MissingInfo),
-            _ => Ok(()),
+        let mut data = self.payload.read().map_err(|_| Error::PayloadLock)?.clone();
+
+        if data.is_object() {
+            deep_merge(&mut data, serde_json::to_value(&item.data)?);
+
+            item.data = serde_json::from_value(data)?;
         }
+
+        Ok(())
     }
-}
 
-#[test]
-fn shutdown_waits_for_items() {
-    use crate::{runtime, types::*};
+    /// Records a telemetry event. Buffered events are attached to the next
+    /// `error` or `critical` item that is queued for delivery.
+    pub fn record_telemetry(&self, event: Telemetry) -> Result<(), Error> {
+        self.telemetry
+            .lock()
+            .map_err(|_| Error::TelemetryLock)?
+            .push(event);
 
-    use wiremock::{
-        matchers::{method, path},
-        Mock, MockServer, ResponseTemplate,
-    };
+        Ok(())
+    }
 
-    let mock_server = runtime::block_on(async { MockServer::start().await }).unwrap();
+    /// Replaces the payload defaults, for example to drop `person` on logout.
+    pub fn set_payload(&self, payload: Value) -> Result<(), Error> {
+        *self.payload.write().map_err(|_| Error::PayloadLock)? = payload;
 
-    let expected_count = QUEUE_DEPTH * 2;
+        Ok(())
+    }
 
-    runtime::block_on(async {
-        Mock::given(method("POST"))
-            .and(path(API_ENDPOINT))
-            .respond_with(ResponseTemplate::new(200))
-            .expect(expected_count as u64)
-            .mount(&mock_server)
-            .await
-    })
-    .unwrap();
+    /// Deep-merges `payload` into the current defaults, for example to add
+    /// `person` after login.
+    pub fn merge_payload(&self, payload: Value) -> Result<(), Error> {
+        let mut current = self.payload.write().map_err(|_| Error::PayloadLock)?;
+
+        if current.is_object() {
+            deep_merge(&mut current, payload);
+        } else {
+            *current = payload;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `item` matches `ignored_messages` or the `check_ignore`
+    /// predicate. Ignored items are counted as dropped.
+    pub fn is_ignored(&self, item: &Item) -> Result<bool, Error> {
+        let check_ignore = self
+            .hooks
+            .read()
+            .map_err(|_| Error::HooksLock)?
+            .check_ignore
+            .clone();
+
+        let ignored = self.ignored_messages.is_match(item.message())
+            || check_ignore.is_some_and(|check_ignore| check_ignore(item));
+
+        if ignored {
+            self.count_dropped(Policy::Ignored);
+        }
+
+        Ok(ignored)
+    }
+
+    /// Registers a predicate that receives every prepared item and returns
+    /// `true` to keep it from being sent. Replaces any previous predicate.
+    pub fn set_check_ignore(
+        &self,
+        check_ignore: impl Fn(&Item) -> bool + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let mut hooks = self.hooks.write().map_err(|_| Error::HooksLock)?;
+
+        hooks.check_ignore = Some(Arc::new(check_ignore));
+
+        Ok(())
+    }
+
+    pub fn clear_check_ignore(&self) -> Result<(), Error> {
+        let mut hooks = self.hooks.write().map_err(|_| Error::HooksLock)?;
+
+        hooks.check_ignore = None;
+
+        Ok(())
+    }
+
+    /// Appends a transform that runs on every item after `prepare_item` and
+    /// before it is filtered and serialized.
+    pub fn add_transform(
+        &self,
+        transform: impl Fn(&mut Item) + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let mut hooks = self.hooks.write().map_err(|_| Error::HooksLock)?;
+
+        hooks.transforms.push(Arc::new(transform));
+
+        Ok(())
+    }
+
+    pub fn clear_transforms(&self) -> Result<(), Error> {
+        let mut hooks = self.hooks.write().map_err(|_| Error::HooksLock)?;
+
+        hooks.transforms.clear();
+
+        Ok(())
+    }
+
+    /// Registers an observer that is told the outcome of every item the
+    /// worker tries to send, including those with a receipt. It runs on the
+    /// worker, so it should return quickly. Replaces any previous observer.
+    pub fn set_on_result(
+        &self,
+        on_result: impl Fn(&Delivery) + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let mut hooks = self.hooks.write().map_err(|_| Error::HooksLock)?;
+
+        hooks.on_result = Some(Arc::new(on_result));
+
+        Ok(())
+    }
+
+    pub fn clear_on_result(&self) -> Result<(), Error> {
+        let mut hooks = self.hooks.write().map_err(|_| Error::HooksLock)?;
+
+        hooks.on_result = None;
+
+        Ok(())
+    }
+
+    async fn transport(&self, item: &Item) -> Result<String, Error> {
+        let (response, result) = match self.post(item).await {
+            Ok(response) => {
+                let result = response.result(item);
+
+                (Some(response), result)
+            }
+            Err(error) => (None, Err(error)),
+        };
+
+        self.notify(item, response.as_ref(), &result);
+
+        result
+    }
+
+    async fn post(&self, item: &Item) -> Result<Response, Error> {
+        let request = PreparedRequest {
+            url: self.endpoint(),
+            headers: vec![
+                (
+                    "X-Rollbar-Access-Token".into(),
+                    self.config.access_token.clone(),
+                ),
+                ("Content-Type".into(), "application/json".into()),
+            ],
+            body: serde_json::to_vec(item)?,
+        };
+
+        let payload_size = request.body.len();
+        let started_ms = runtime::now_ms();
+
+        let response = self.sender.send(request).await;
+
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.request(runtime::now_ms().saturating_sub(started_ms), payload_size);
+        }
+
+        response
+    }
+
+    /// Tells the `on_result` observer, if there is one, how `item` fared.
+    fn notify(&self, item: &Item, response: Option<&Response>, result: &Result<String, Error>) {
+        let on_result = match self.hooks.read() {
+            Ok(hooks) => hooks.on_result.clone(),
+            Err(_) => return,
+        };
+
+        if let Some(on_result) = on_result {
+            on_result(&Delivery {
+                uuid: result.as_deref().ok().or(item.data.uuid.as_deref()),
+                status: response.map(|response| response.status),
+                body: response.map(|response| response.body.as_str()),
+                error: result.as_ref().err(),
+            });
+        }
+    }
+}
+
+impl Response {
+    /// The uuid Rollbar assigned the occurrence, falling back to the item's
+    /// own, or the error the status stands for.
+    fn result(&self, item: &Item) -> Result<String, Error> {
+        let rejection = || Rejection {
+            status: self.status,
+            body: self.body.clone(),
+        };
+
+        match StatusCode::from_u16(self.status) {
+            // TODO: truncate payload... but truncate what?
+            Ok(StatusCode::PAYLOAD_TOO_LARGE) => Err(Error::PayloadTooLarge(rejection())),
+            // TODO: wait for x to retry.
+            Ok(StatusCode::TOO_MANY_REQUESTS) => Err(Error::RateLimited(rejection())),
+            // TODO: don't send requests until reconfigured with new token?
+            Ok(StatusCode::FORBIDDEN) => Err(Error::AccessDenied(rejection())),
+            // TODO: possibly unnecessary if library guaranteed to not send invalid requests.
+            Ok(StatusCode::UNPROCESSABLE_ENTITY) => Err(Error::MissingInfo(rejection())),
+            Ok(status) if status.is_success() => {
+                let uuid = match serde_json::from_str::<ApiResponse>(&self.body) {
+                    Ok(ApiResponse {
+                        result: Some(ApiResult { uuid: Some(uuid) }),
+                    }) => uuid,
+                    _ => item.data.uuid.clone().unwrap_or_default(),
+                };
+
+                Ok(uuid)
+            }
+            _ => Err(Error::Rejected(rejection())),
+        }
+    }
+}
+
+/// Merges `overlay` into `base`. Objects are merged key by key; any other
+/// value in `overlay` replaces the one in `base`.
+fn deep_merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                deep_merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (_, Value::Null) => {}
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[test]
+fn shutdown_waits_for_items() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let expected_count = QUEUE_DEPTH * 2;
 
     let items = (0..expected_count)
         .map(|i| Item::from((Level::Info, format!("{}", i), Default::default())));
 
+    let transport = RecordingTransport::new().unwrap();
+
+    for item in items {
+        transport.send(item).unwrap();
+    }
+
+    transport.shutdown().unwrap();
+
+    assert_eq!(transport.sender().items().len(), expected_count);
+}
+
+#[test]
+fn rate_limited_items_are_suppressed() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let max_items = 5;
+
+    let config = Config::builder()
+        .access_token("abc")
+        .max_items(max_items)
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    for i in 0..max_items * 2 {
+        let item = Item::from((Level::Error, format!("{}", i), Default::default()));
+
+        transport.send(item).unwrap();
+    }
+
+    transport.shutdown().unwrap();
+
+    // Every allowed item plus the single "limit reached" item.
+    assert_eq!(transport.sender().items().len(), max_items as usize + 1);
+    assert_eq!(transport.suppressed_count().unwrap(), max_items as u64);
+}
+
+#[test]
+fn duplicates_are_folded_into_summary() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let config = Config::builder()
+        .access_token("abc")
+        .dedup_window_ms(60_000)
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    for _ in 0..10 {
+        let item = Item::from((Level::Error, "crash loop", Default::default()));
+
+        transport.send(item).unwrap();
+    }
+
+    transport.shutdown().unwrap();
+
+    let counts: Vec<_> = transport
+        .sender()
+        .items()
+        .iter()
+        .map(|item| {
+            item.data
+                .custom
+                .as_ref()
+                .and_then(|custom| custom.get("occurrence_count").cloned())
+        })
+        .collect();
+
+    assert_eq!(counts, [None, Some(9.into())]);
+}
+
+#[test]
+fn ignored_items_are_not_sent() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let config = Config::builder()
+        .access_token("abc")
+        .environment(Some("test".to_owned()))
+        .ignored_messages(vec!["^chrome-extension://".to_owned()])
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    transport
+        .set_check_ignore(|item| {
+            item.data.environment.as_deref() == Some("test") && item.message().contains("veto")
+        })
+        .unwrap();
+
+    for message in ["chrome-extension://abc failed", "please veto", "keep me"] {
+        let item = Item::from((Level::Error, message, Default::default()));
+
+        transport.send(item).unwrap();
+    }
+
+    transport.shutdown().unwrap();
+
+    let items = transport.sender().items();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].message(), "keep me");
+}
+
+#[test]
+fn transforms_run_before_send() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let config = Config::builder()
+        .access_token("abc")
+        .environment(Some("production".to_owned()))
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    transport
+        .add_transform(|item| item.data.environment = Some("staging".into()))
+        .unwrap();
+
+    transport
+        .add_transform(|item| {
+            item.data
+                .custom
+                .get_or_insert_with(Default::default)
+                .insert("deploy_id".into(), "42".into());
+        })
+        .unwrap();
+
+    let item = Item::from((Level::Info, "deployed", Default::default()));
+
+    transport.send(item).unwrap();
+
+    transport.shutdown().unwrap();
+
+    let items = transport.sender().items();
+    assert_eq!(items[0].data.environment.as_deref(), Some("staging"));
+    assert_eq!(items[0].data.custom.as_ref().unwrap()["deploy_id"], "42");
+}
+
+#[test]
+fn deep_merge_prefers_overlay() {
+    use serde_json::json;
+
+    let mut base = json!({
+        "custom": { "team": "web", "tier": 1 },
+        "server": { "host": "web1" },
+    });
+
+    deep_merge(
+        &mut base,
+        json!({
+            "custom": { "tier": 2, "request_id": "abc" },
+            "environment": "prod",
+        }),
+    );
+
+    assert_eq!(
+        base,
+        json!({
+            "custom": { "team": "web", "tier": 2, "request_id": "abc" },
+            "server": { "host": "web1" },
+            "environment": "prod",
+        })
+    );
+}
+
+#[test]
+fn payload_defaults_are_merged_into_items() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let config = Config::builder()
+        .access_token("abc")
+        .payload(serde_json::json!({
+            "custom": { "team": "web", "tier": "default" },
+            "server": { "host": "web1" }
+        }))
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    transport
+        .merge_payload(serde_json::json!({ "person": { "id": "7" } }))
+        .unwrap();
+
+    let mut item = Item::from((Level::Info, "logged in", Default::default()));
+
+    item.data.custom = Some([("tier".to_owned(), "item".into())].into_iter().collect());
+
+    transport.send(item).unwrap();
+
+    transport.shutdown().unwrap();
+
+    let data = serde_json::to_value(&transport.sender().items()[0].data).unwrap();
+
+    assert_eq!(
+        data["custom"],
+        serde_json::json!({ "team": "web", "tier": "item" })
+    );
+    assert_eq!(data["server"], serde_json::json!({ "host": "web1" }));
+    assert_eq!(data["person"], serde_json::json!({ "id": "7" }));
+}
+
+#[test]
+fn deliver_resolves_with_uuid() {
+    use crate::{runtime, types::*};
+
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    let mock_server = runtime::block_on(async { MockServer::start().await }).unwrap();
+
+    runtime::block_on(async {
+        Mock::given(method("POST"))
+            .and(path(API_ENDPOINT))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "err": 0,
+                "result": { "id": null, "uuid": "from-rollbar" }
+            })))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path(API_ENDPOINT))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&mock_server)
+            .await;
+    })
+    .unwrap();
+
     let config = Config::builder()
         .uri(mock_server.uri())
         .access_token("abc")
@@ -257,9 +1447,451 @@ fn shutdown_waits_for_items() {
 
     let transport = Transport::new(config).unwrap();
 
-    for item in items {
+    let deliver = |message: &str| {
+        let mut item = Item::from((Level::Error, message, Default::default()));
+
+        transport.prepare(&mut item).unwrap();
+
+        runtime::block_on(transport.deliver(item)).unwrap()
+    };
+
+    assert_eq!(
+        deliver("accepted").unwrap(),
+        Some("from-rollbar".to_owned())
+    );
+    assert_eq!(deliver("denied").unwrap_err().code(), "AccessDenied");
+
+    transport.shutdown().unwrap();
+}
+
+#[test]
+fn deliver_within_times_out() {
+    use crate::{runtime, types::*};
+
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    let mock_server = runtime::block_on(async { MockServer::start().await }).unwrap();
+
+    runtime::block_on(async {
+        Mock::given(method("POST"))
+            .and(path(API_ENDPOINT))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+            .mount(&mock_server)
+            .await;
+    })
+    .unwrap();
+
+    let config = Config::builder()
+        .uri(mock_server.uri())
+        .access_token("abc")
+        .build();
+
+    let transport = Transport::new(config).unwrap();
+
+    let mut item = Item::from((Level::Critical, "uncaught", Default::default()));
+    transport.prepare(&mut item).unwrap();
+
+    let result =
+        runtime::block_on(transport.deliver_within(item, Duration::from_millis(50))).unwrap();
+
+    assert_eq!(result.unwrap_err().code(), "Timeout");
+
+    transport.shutdown().unwrap();
+}
+
+#[test]
+fn telemetry_is_attached_to_next_error() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let config = Config::builder()
+        .access_token("abc")
+        .max_telemetry_events(2)
+        .ignored_messages(vec!["^noise".to_owned()])
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    for message in ["one", "two", "three"] {
+        let event = Event::Log {
+            message: message.into(),
+        };
+
+        transport
+            .record_telemetry(Telemetry::new(Level::Info, event, 0))
+            .unwrap();
+    }
+
+    // Neither an info item nor an ignored error takes the events.
+    for (level, message) in [
+        (Level::Info, "info"),
+        (Level::Error, "noise"),
+        (Level::Error, "error"),
+        (Level::Error, "next"),
+    ] {
+        transport
+            .send(Item::from((level, message, Default::default())))
+            .unwrap();
+    }
+
+    transport.shutdown().unwrap();
+
+    let telemetry: Vec<(String, Vec<String>)> = transport
+        .sender()
+        .items()
+        .iter()
+        .map(|item| {
+            let messages = item
+                .data
+                .body
+                .telemetry
+                .iter()
+                .map(|telemetry| match &telemetry.event {
+                    Event::Log { message } => message.clone(),
+                    other => panic!("unexpected event: {:?}", other),
+                })
+                .collect();
+
+            (item.message().to_owned(), messages)
+        })
+        .collect();
+
+    assert_eq!(
+        telemetry,
+        [
+            ("info".to_owned(), vec![]),
+            (
+                "error".to_owned(),
+                vec!["two".to_owned(), "three".to_owned()]
+            ),
+            ("next".to_owned(), vec![]),
+        ]
+    );
+}
+
+#[test]
+fn pending_items_can_be_taken() {
+    use crate::{runtime, types::*};
+
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    let mock_server = runtime::block_on(async { MockServer::start().await }).unwrap();
+
+    runtime::block_on(async {
+        Mock::given(method("POST"))
+            .and(path(API_ENDPOINT))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(200)))
+            .mount(&mock_server)
+            .await;
+    })
+    .unwrap();
+
+    let config = Config::builder()
+        .uri(mock_server.uri())
+        .access_token("abc")
+        .build();
+
+    let transport = Transport::new(config).unwrap();
+
+    for message in ["one", "two", "three"] {
+        transport
+            .send(Item::from((Level::Error, message, Default::default())))
+            .unwrap();
+    }
+
+    std::thread::sleep(Duration::from_millis(50));
+
+    let pending = transport.take_pending().unwrap();
+    assert_eq!(pending.len(), 3);
+    assert!(transport.take_pending().unwrap().is_empty());
+
+    let body: Value =
+        serde_json::from_str(&transport.body_with_token(&pending[0]).unwrap()).unwrap();
+    assert_eq!(body["access_token"], "abc");
+    assert_eq!(body["data"]["body"]["message"]["body"], "one");
+
+    transport.shutdown().unwrap();
+
+    let received = runtime::block_on(mock_server.received_requests())
+        .unwrap()
+        .unwrap();
+
+    // Only an item already on its way when they were taken is sent.
+    assert!(received.len() <= 1);
+}
+
+#[test]
+fn shutdown_reports_fast_failures() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    // The worker can finish with an item before `send` returns, which must
+    // not lose its error.
+    for _ in 0..10 {
+        let transport = RecordingTransport::new().unwrap();
+
+        transport.sender().respond_with(403, "");
+
+        transport
+            .send(Item::from((Level::Info, "denied", Default::default())))
+            .unwrap();
+
+        assert!(matches!(transport.shutdown(), Err(Error::Shutdown(_))));
+    }
+}
+
+#[test]
+fn flush_after_close_returns() {
+    use crate::{runtime, testing::RecordingTransport, types::*};
+
+    let transport = RecordingTransport::new().unwrap();
+
+    transport.shutdown().unwrap();
+
+    assert!(transport
+        .send(Item::from((Level::Error, "too late", Default::default())))
+        .is_err());
+
+    let flushed = runtime::block_on(async {
+        tokio::time::timeout(Duration::from_secs(5), transport.flush()).await
+    })
+    .unwrap();
+
+    assert!(matches!(flushed, Ok(Ok(()))));
+    assert_eq!(transport.stats().unwrap().queue_depth, 0);
+}
+
+#[test]
+fn items_refused_after_close_are_not_pending() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let transport = RecordingTransport::new().unwrap();
+
+    transport.shutdown().unwrap();
+
+    assert!(transport
+        .send(Item::from((Level::Error, "too late", Default::default())))
+        .is_err());
+
+    assert!(transport.take_pending().unwrap().is_empty());
+    assert!(transport
+        .metrics_text()
+        .unwrap()
+        .contains("rollbar_items_enqueued_total 0\n"));
+}
+
+#[test]
+fn on_result_observes_every_attempt() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let transport = RecordingTransport::new().unwrap();
+
+    transport.sender().respond_with(
+        200,
+        r#"{"err":0,"result":{"id":null,"uuid":"from-rollbar"}}"#,
+    );
+    transport.sender().respond_with(403, "denied");
+
+    let deliveries = Arc::new(Mutex::new(vec![]));
+    let observed = deliveries.clone();
+
+    transport
+        .set_on_result(move |delivery| {
+            observed.lock().unwrap().push((
+                delivery.uuid.map(str::to_owned),
+                delivery.status,
+                delivery.body.map(str::to_owned),
+                delivery.error.map(Error::code),
+            ));
+        })
+        .unwrap();
+
+    for message in ["accepted", "denied"] {
+        let mut item = Item::from((Level::Error, message, Default::default()));
+        item.data.uuid = Some(message.to_owned());
+
         transport.send(item).unwrap();
     }
 
+    assert!(transport.shutdown().is_err());
+
+    let deliveries = deliveries.lock().unwrap();
+
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0].0.as_deref(), Some("from-rollbar"));
+    assert_eq!(deliveries[0].1, Some(200));
+    assert_eq!(deliveries[0].3, None);
+    assert_eq!(
+        deliveries[1],
+        (
+            Some("denied".to_owned()),
+            Some(403),
+            Some("denied".to_owned()),
+            Some("AccessDenied")
+        )
+    );
+}
+
+#[test]
+fn stats_keep_rejections() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let config = Config::builder()
+        .access_token("abc")
+        .max_items(3)
+        .max_recent_errors(1)
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    transport.sender().respond_with(200, "");
+
+    for _ in 0..3 {
+        transport.sender().respond_with(500, "unavailable");
+    }
+
+    for message in ["one", "two", "three", "four"] {
+        transport
+            .send(Item::from((Level::Error, message, Default::default())))
+            .unwrap();
+    }
+
+    match transport.shutdown() {
+        Err(Error::Shutdown(errors)) => assert!(errors.contains("and 2 earlier errors")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let stats = transport.stats().unwrap();
+
+    // The "limit reached" notice is sent, and rejected, too.
+    assert_eq!((stats.sent, stats.failed, stats.dropped), (1, 3, 1));
+    assert_eq!(stats.queue_depth, 0);
+
+    let last_error = stats.last_error.unwrap();
+    assert_eq!(last_error.code, "Rejected");
+    assert_eq!(last_error.status, Some(500));
+    assert_eq!(last_error.body.as_deref(), Some("unavailable"));
+
+    assert_eq!(transport.recent_errors().unwrap(), vec![last_error]);
+}
+
+#[test]
+fn bindings_count_their_own_ignores() {
+    let transport = Transport::new(Config::builder().access_token("abc").build()).unwrap();
+
+    transport.count_ignored();
+
+    assert!(transport
+        .metrics_text()
+        .unwrap()
+        .contains("rollbar_items_dropped_total{policy=\"ignored\"} 1\n"));
+}
+
+#[test]
+fn metrics_count_outcomes() {
+    use crate::{testing::RecordingTransport, types::*};
+
+    let config = Config::builder()
+        .access_token("abc")
+        .ignored_messages(vec!["^noise".to_owned()])
+        .build();
+
+    let transport = RecordingTransport::with_config(config).unwrap();
+
+    transport.sender().respond_with(200, "");
+    transport.sender().respond_with(429, "");
+
+    for message in ["accepted", "limited", "noise"] {
+        transport
+            .send(Item::from((Level::Error, message, Default::default())))
+            .unwrap();
+    }
+
+    assert!(transport.shutdown().is_err());
+
+    let text = transport.metrics_text().unwrap();
+
+    for line in [
+        "rollbar_items_enqueued_total 2\n",
+        "rollbar_items_sent_total 1\n",
+        "rollbar_items_failed_total{reason=\"RateLimited\"} 1\n",
+        "rollbar_items_dropped_total{policy=\"ignored\"} 1\n",
+        "rollbar_queue_depth 0\n",
+        "rollbar_http_request_duration_seconds_count 2\n",
+        "rollbar_payload_size_bytes_count 2\n",
+    ] {
+        assert!(text.contains(line), "missing {:?} in\n{}", line, text);
+    }
+
+    #[cfg(feature = "metrics")]
+    {
+        let registry = prometheus::Registry::new();
+
+        transport.register_metrics(&registry).unwrap();
+
+        let families = registry.gather();
+        let sent = families
+            .iter()
+            .find(|family| family.get_name() == "rollbar_items_sent_total")
+            .unwrap();
+
+        assert_eq!(sent.get_metric()[0].get_counter().get_value(), 1.0);
+    }
+}
+
+#[test]
+fn custom_sender_delivers_items() {
+    use crate::{runtime, sender::*, types::*};
+
+    struct Canned {
+        requests: Arc<Mutex<Vec<PreparedRequest>>>,
+    }
+
+    impl Sender for Canned {
+        fn send(&self, request: PreparedRequest) -> SendFuture<'_> {
+            self.requests.lock().unwrap().push(request);
+
+            Box::pin(async {
+                Ok(Response {
+                    status: 200,
+                    body: r#"{"err":0,"result":{"id":null,"uuid":"from-sender"}}"#.into(),
+                })
+            })
+        }
+    }
+
+    let requests = Arc::new(Mutex::new(vec![]));
+
+    let config = Config::builder().access_token("abc").build();
+
+    let transport = Transport::with_sender(
+        config,
+        Canned {
+            requests: requests.clone(),
+        },
+    )
+    .unwrap();
+
+    let mut item = Item::from((Level::Error, "custom", Default::default()));
+
+    transport.prepare(&mut item).unwrap();
+
+    assert_eq!(
+        runtime::block_on(transport.deliver(item)).unwrap().unwrap(),
+        Some("from-sender".to_owned())
+    );
+
     transport.shutdown().unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].url.ends_with(API_ENDPOINT));
+    assert!(requests[0]
+        .headers
+        .contains(&("X-Rollbar-Access-Token".to_owned(), "abc".to_owned())));
+    assert!(String::from_utf8_lossy(&requests[0].body).contains("custom"));
 }
@@ -1,14 +1,18 @@
-use ::{serde::Serialize, serde_json::Value, std::collections::HashMap};
+use ::{
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    std::collections::HashMap,
+};
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Item {
     pub data: Data,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Data {
     pub body: Body,
     pub level: Level,
@@ -20,9 +24,19 @@ pub struct Data {
     pub code_version: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub environment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom: Option<HashMap<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// Any other fields of the item schema, such as `person`, `server` or
+    /// `client`.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[cfg_attr(feature = "c", repr(u8))]
@@ -48,36 +62,140 @@ impl<Str: AsRef<str>> From<Str> for Level {
     }
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Body {
+    #[serde(flatten)]
+    pub content: Content,
+    /// Events leading up to the item, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub telemetry: Vec<Telemetry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
-pub enum Body {
+pub enum Content {
     Message(Message),
     Trace(Trace),
+    /// Traces of an error and the errors that caused it, most recent first.
+    TraceChain(Vec<Trace>),
+}
+
+/// A breadcrumb recorded before an item was reported, following the
+/// telemetry schema of `item.js`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Telemetry {
+    pub level: Level,
+    pub source: String,
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "body", rename_all = "lowercase")]
+pub enum Event {
+    Log {
+        message: String,
+    },
+    Network {
+        subtype: String,
+        method: String,
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        status_code: Option<u16>,
+        start_timestamp_ms: u64,
+        end_timestamp_ms: u64,
+    },
+    Navigation {
+        from: String,
+        to: String,
+    },
+    Dom {
+        subtype: String,
+        element: String,
+    },
+}
+
+impl Telemetry {
+    pub fn new(level: Level, event: Event, timestamp_ms: u64) -> Self {
+        Self {
+            level,
+            source: "client".into(),
+            timestamp_ms,
+            event,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Message {
     body: String,
     #[serde(flatten)]
     extra: HashMap<String, Value>,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Trace {
     frames: Vec<Frame>,
     exception: Exception,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Frame {
     filename: String,
     lineno: Option<u32>,
     colno: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Exception {
     class: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl Trace {
+    /// `frames` are ordered oldest first, so the frame that raised the
+    /// exception is last.
+    pub fn new(frames: Vec<Frame>, exception: Exception) -> Self {
+        Self { frames, exception }
+    }
+
+    /// Appends `frame` as the newest frame.
+    pub fn push(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+}
+
+impl Frame {
+    pub fn new(
+        filename: impl Into<String>,
+        lineno: Option<u32>,
+        colno: Option<u32>,
+        method: Option<String>,
+    ) -> Self {
+        Self {
+            filename: filename.into(),
+            lineno,
+            colno,
+            method,
+        }
+    }
+
+    pub(crate) fn has_position(&self) -> bool {
+        self.lineno.is_some()
+    }
+}
+
+impl Exception {
+    pub fn new(class: impl Into<String>, message: Option<String>) -> Self {
+        Self {
+            class: class.into(),
+            message,
+        }
+    }
 }
 
 impl<AsStr: Into<String>> From<(Level, AsStr, HashMap<String, Value>)> for Item {
@@ -89,17 +207,137 @@ impl<AsStr: Into<String>> From<(Level, AsStr, HashMap<String, Value>)> for Item
 
         Self {
             data: Data {
-                body: Body::Message(message),
+                body: Body::from(Content::Message(message)),
                 level,
                 context: None,
                 language: None,
                 code_version: None,
                 environment: None,
+                fingerprint: None,
+                custom: None,
+                uuid: None,
+                extra: HashMap::new(),
             },
         }
     }
 }
 
+impl Item {
+    /// Builds a `trace` item from a single trace, or a `trace_chain` when the
+    /// error had causes. Traces have no room for extra data, so it is
+    /// reported under `custom` instead.
+    pub fn from_traces(
+        level: Level,
+        mut traces: Vec<Trace>,
+        extra: HashMap<String, Value>,
+    ) -> Self {
+        let body = if traces.len() == 1 {
+            Content::Trace(traces.remove(0))
+        } else {
+            Content::TraceChain(traces)
+        };
+
+        Self {
+            data: Data {
+                body: Body::from(body),
+                level,
+                context: None,
+                language: None,
+                code_version: None,
+                environment: None,
+                fingerprint: None,
+                custom: if extra.is_empty() { None } else { Some(extra) },
+                uuid: None,
+                extra: HashMap::new(),
+            },
+        }
+    }
+
+    /// The human readable text of the item: the message body or, for traces,
+    /// the message of the most recent exception (its class if it has none).
+    pub(crate) fn message(&self) -> &str {
+        match &self.data.body.content {
+            Content::Message(message) => &message.body,
+            Content::Trace(trace) => trace.exception.text(),
+            Content::TraceChain(traces) => traces
+                .first()
+                .map(|trace| trace.exception.text())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Identifies occurrences that should be treated as duplicates of one
+    /// another: the level, the message body (or exception class and most
+    /// recent frame) and the fingerprint.
+    pub(crate) fn signature(&self) -> Signature {
+        let content = match &self.data.body.content {
+            Content::Message(message) => ContentSignature::Message(message.body.clone()),
+            Content::Trace(trace) => ContentSignature::Trace(trace.signature()),
+            Content::TraceChain(traces) => {
+                ContentSignature::TraceChain(traces.iter().map(Trace::signature).collect())
+            }
+        };
+
+        Signature {
+            level: self.data.level.clone(),
+            content,
+            fingerprint: self.data.fingerprint.clone(),
+        }
+    }
+}
+
+/// What `Item::signature` compares. Kept as separate fields rather than
+/// joined into a string, so no message or fingerprint can mimic another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Signature {
+    level: Level,
+    content: ContentSignature,
+    fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ContentSignature {
+    Message(String),
+    Trace(TraceSignature),
+    TraceChain(Vec<TraceSignature>),
+}
+
+/// The exception class and the file, line and column of the most recent
+/// frame.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TraceSignature {
+    class: String,
+    frame: Option<(String, Option<u32>, Option<u32>)>,
+}
+
+impl From<Content> for Body {
+    fn from(content: Content) -> Self {
+        Self {
+            content,
+            telemetry: vec![],
+        }
+    }
+}
+
+impl Trace {
+    fn signature(&self) -> TraceSignature {
+        TraceSignature {
+            class: self.exception.class.clone(),
+            frame: self
+                .frames
+                .last()
+                .map(|frame| (frame.filename.clone(), frame.lineno, frame.colno)),
+        }
+    }
+}
+
+impl Exception {
+    /// The exception message, falling back to its class.
+    fn text(&self) -> &str {
+        self.message.as_deref().unwrap_or(&self.class)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,19 +348,23 @@ mod tests {
 
         let input = Item {
             data: Data {
-                body: Body::Message(Message {
+                body: Body::from(Content::Message(Message {
                     body: "this is a test".into(),
                     extra: {
                         let mut extra = HashMap::new();
                         extra.insert("extra_data".into(), "right here".into());
                         extra
                     },
-                }),
+                })),
                 level: Level::Info,
                 language: None,
                 context: None,
                 code_version: None,
                 environment: None,
+                fingerprint: None,
+                custom: None,
+                uuid: None,
+                extra: HashMap::new(),
             },
         };
 
@@ -142,4 +384,74 @@ mod tests {
 
         assert_eq!(got, want);
     }
+
+    #[test]
+    fn telemetry_round_trips_alongside_content() {
+        use serde_json::{from_value, json, to_value};
+
+        let mut item = Item::from((Level::Error, "boom", HashMap::new()));
+        item.data.body.telemetry.push(Telemetry::new(
+            Level::Info,
+            Event::Navigation {
+                from: "/".into(),
+                to: "/checkout".into(),
+            },
+            42,
+        ));
+
+        let value = to_value(&item).unwrap();
+
+        assert_eq!(
+            value["data"]["body"],
+            json!({
+                "message": { "body": "boom" },
+                "telemetry": [{
+                    "level": "info",
+                    "type": "navigation",
+                    "source": "client",
+                    "timestamp_ms": 42,
+                    "body": { "from": "/", "to": "/checkout" }
+                }]
+            })
+        );
+
+        assert_eq!(from_value::<Item>(value).unwrap(), item);
+    }
+
+    #[test]
+    fn network_telemetry_uses_the_item_schema() {
+        use serde_json::{json, to_value};
+
+        let telemetry = Telemetry::new(
+            Level::Error,
+            Event::Network {
+                subtype: "xhr".into(),
+                method: "GET".into(),
+                url: "https://example.com".into(),
+                status_code: Some(500),
+                start_timestamp_ms: 1500413394557,
+                end_timestamp_ms: 1500413394957,
+            },
+            42,
+        );
+
+        // The keys `src/item.js` documents for network events.
+        assert_eq!(
+            to_value(&telemetry).unwrap(),
+            json!({
+                "level": "error",
+                "type": "network",
+                "source": "client",
+                "timestamp_ms": 42,
+                "body": {
+                    "subtype": "xhr",
+                    "method": "GET",
+                    "url": "https://example.com",
+                    "status_code": 500,
+                    "start_timestamp_ms": 1500413394557u64,
+                    "end_timestamp_ms": 1500413394957u64
+                }
+            })
+        );
+    }
 }
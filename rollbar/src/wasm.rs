@@ -1,7 +1,15 @@
-use ::{serde_json::Value, std::collections::HashMap, wasm_bindgen::prelude::*};
+use ::{
+    serde_json::{json, Map, Value},
+    std::{cell::RefCell, collections::HashMap, rc::Rc},
+    wasm_bindgen::{prelude::*, JsCast},
+    wasm_bindgen_futures::{future_to_promise, JsFuture},
+    web_sys::{ErrorEvent, PromiseRejectionEvent},
+};
 
 use crate::{
-    types::{Item, Level},
+    instrument, stack,
+    transport::Error,
+    types::{Exception, Frame, Item, Level, Trace},
     Config, Transport,
 };
 
@@ -9,51 +17,640 @@ use crate::{
 #[wasm_bindgen]
 pub struct Instance {
     transport: Transport,
+    check_ignore: Rc<RefCell<Option<js_sys::Function>>>,
+    transforms: Rc<RefCell<Vec<js_sys::Function>>>,
 }
 
 #[wasm_bindgen]
 impl Instance {
     #[wasm_bindgen(js_name = "fromConfig")]
     pub fn from_config(input: JsValue) -> Result<Instance, JsValue> {
-        let config: Config = input
-            .into_serde()
+        let config: Config = serde_wasm_bindgen::from_value(input)
             .map_err(|error| JsValue::from(format!("invalid config object: {}", error)))?;
 
+        let capture_uncaught = config.capture_uncaught;
+        let capture_unhandled_rejections = config.capture_unhandled_rejections;
+        let auto_instrument = config.auto_instrument;
+        let endpoint = config.uri.clone();
+        let persist_queue = config.persist_queue;
+
         let transport = Transport::new(config)
             .map_err(|error| JsValue::from(format!("unable to create transport: {}", error)))?;
 
-        Ok(Instance { transport })
+        let instance = Instance {
+            transport,
+            check_ignore: Rc::new(RefCell::new(None)),
+            transforms: Rc::new(RefCell::new(vec![])),
+        };
+
+        if capture_uncaught {
+            instance.listen_for_errors()?;
+        }
+
+        if capture_unhandled_rejections {
+            instance.listen_for_rejections()?;
+        }
+
+        instrument::install(&instance.transport, &endpoint, auto_instrument)?;
+
+        instance.listen_for_unload()?;
+
+        if persist_queue {
+            let transport = instance.transport.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(error) = transport.restore().await {
+                    web_sys::console::error_1(
+                        &format!("unable to send persisted items: {}", error).into(),
+                    );
+                }
+            });
+        }
+
+        Ok(instance)
+    }
+
+    /// Registers a function that receives every prepared item and returns
+    /// `true` to keep it from being sent. Pass `undefined` to remove it.
+    #[wasm_bindgen(js_name = "setCheckIgnore")]
+    pub fn set_check_ignore(&self, callback: Option<js_sys::Function>) {
+        self.check_ignore.replace(callback);
+    }
+
+    /// Registers a function that is called with `{ uuid, status, body, error }`
+    /// for every item the transport tries to send. Pass `undefined` to remove
+    /// it.
+    #[wasm_bindgen(js_name = "setOnResult")]
+    pub fn set_on_result(&self, callback: Option<js_sys::Function>) -> Result<(), JsValue> {
+        let callback = match callback {
+            Some(callback) => Callback(callback),
+            None => return self.transport.clear_on_result().map_err(js_error),
+        };
+
+        self.transport
+            .set_on_result(move |delivery| {
+                let result = js_sys::Object::new();
+
+                let fields = [
+                    ("uuid", delivery.uuid.map(JsValue::from)),
+                    ("status", delivery.status.map(JsValue::from)),
+                    ("body", delivery.body.map(JsValue::from)),
+                ];
+
+                for (key, value) in fields {
+                    if let Some(value) = value {
+                        let _ = js_sys::Reflect::set(&result, &key.into(), &value);
+                    }
+                }
+
+                if let Some(error) = delivery.error {
+                    let _ = js_sys::Reflect::set(&result, &"error".into(), &js_error_ref(error));
+                }
+
+                if let Err(error) = callback.0.call1(&JsValue::NULL, &result) {
+                    web_sys::console::error_2(&"onResult callback threw:".into(), &error);
+                }
+            })
+            .map_err(js_error)
+    }
+
+    /// Replaces the defaults deep-merged into every item.
+    #[wasm_bindgen(js_name = "setPayload")]
+    pub fn set_payload(&self, payload: JsValue) -> Result<(), JsValue> {
+        let payload: Value = serde_wasm_bindgen::from_value(payload)
+            .map_err(|error| JsValue::from(format!("invalid payload: {}", error)))?;
+
+        self.transport
+            .set_payload(payload)
+            .map_err(|error| JsValue::from(format!("{}", error)))
+    }
+
+    /// Deep-merges `payload` into the defaults, e.g. to add `person` after
+    /// login.
+    #[wasm_bindgen(js_name = "mergePayload")]
+    pub fn merge_payload(&self, payload: JsValue) -> Result<(), JsValue> {
+        let payload: Value = serde_wasm_bindgen::from_value(payload)
+            .map_err(|error| JsValue::from(format!("invalid payload: {}", error)))?;
+
+        self.transport
+            .merge_payload(payload)
+            .map_err(|error| JsValue::from(format!("{}", error)))
+    }
+
+    /// Appends a function that runs on every prepared item. It may either
+    /// mutate the item it receives or return a replacement.
+    #[wasm_bindgen(js_name = "addTransform")]
+    pub fn add_transform(&self, transform: js_sys::Function) {
+        self.transforms.borrow_mut().push(transform);
+    }
+
+    fn transform(&self, item: Item) -> Result<Item, JsValue> {
+        let transforms = self.transforms.borrow().clone();
+
+        if transforms.is_empty() {
+            return Ok(item);
+        }
+
+        let mut value = to_js(&item)?;
+
+        for transform in transforms {
+            let result = transform.call1(&JsValue::NULL, &value)?;
+
+            if !result.is_undefined() {
+                value = result;
+            }
+        }
+
+        serde_wasm_bindgen::from_value(value).map_err(|error| JsValue::from(format!("{}", error)))
+    }
+
+    /// Runs `item` through the pipeline, returning `None` if it's ignored.
+    fn pipeline(&self, mut item: Item) -> Result<Option<Item>, JsValue> {
+        self.add_browser_data(&mut item);
+
+        self.transport.prepare(&mut item).map_err(js_error)?;
+
+        let item = self.transform(item)?;
+
+        if self.is_ignored(&item)? {
+            return Ok(None);
+        }
+
+        Ok(Some(item))
+    }
+
+    /// Queues `item` without blocking. The promise resolves with the
+    /// occurrence uuid, or `undefined` if the item was ignored, folded into a
+    /// duplicate or rate limited. It rejects with an `Error` whose `code`
+    /// names the failure.
+    fn report(&self, item: Item) -> js_sys::Promise {
+        let item = match self.pipeline(item) {
+            Ok(Some(item)) => item,
+            Ok(None) => return js_sys::Promise::resolve(&JsValue::UNDEFINED),
+            Err(error) => return js_sys::Promise::reject(&error),
+        };
+
+        let transport = self.transport.clone();
+
+        future_to_promise(async move {
+            match transport.deliver(item).await {
+                Ok(uuid) => Ok(uuid.map(JsValue::from).unwrap_or(JsValue::UNDEFINED)),
+                Err(error) => Err(js_error(error)),
+            }
+        })
+    }
+
+    /// Reports `item` from an event listener, where a rejection would go
+    /// unhandled and be reported in turn.
+    fn report_in_background(&self, item: Result<Item, JsValue>, context: &'static str) {
+        let promise = match item {
+            Ok(item) => self.report(item),
+            Err(error) => js_sys::Promise::reject(&error),
+        };
+
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(error) = JsFuture::from(promise).await {
+                web_sys::console::error_2(&context.into(), &error);
+            }
+        });
+    }
+
+    /// Reports every uncaught error that reaches `window`.
+    fn listen_for_errors(&self) -> Result<(), JsValue> {
+        let this = self.clone();
+
+        let listener = Closure::wrap(Box::new(move |event: ErrorEvent| {
+            this.report_in_background(
+                item_from_error_event(&event),
+                "unable to report uncaught error:",
+            );
+        }) as Box<dyn FnMut(ErrorEvent)>);
+
+        add_window_listener("error", listener)
+    }
+
+    /// Reports the reason of every promise rejection nothing handled.
+    fn listen_for_rejections(&self) -> Result<(), JsValue> {
+        let this = self.clone();
+
+        let listener = Closure::wrap(Box::new(move |event: PromiseRejectionEvent| {
+            this.report_in_background(
+                item_from_value(Level::Error, event.reason(), HashMap::new()),
+                "unable to report unhandled rejection:",
+            );
+        }) as Box<dyn FnMut(PromiseRejectionEvent)>);
+
+        add_window_listener("unhandledrejection", listener)
+    }
+
+    /// Fills in `client.javascript` and `request` from the page, leaving any
+    /// value the item already has in place.
+    fn add_browser_data(&self, item: &mut Item) {
+        let window = match web_sys::window() {
+            Some(window) => window,
+            None => return,
+        };
+
+        let config = self.transport.config();
+        let navigator = window.navigator();
+
+        let mut javascript = Map::new();
+
+        if let Ok(user_agent) = navigator.user_agent() {
+            javascript.insert("browser".into(), user_agent.into());
+        }
+
+        if let Some(language) = navigator.language() {
+            javascript.insert("language".into(), language.into());
+        }
+
+        if let Ok(screen) = window.screen() {
+            if let (Ok(width), Ok(height)) = (screen.width(), screen.height()) {
+                javascript.insert("screen".into(), json!({ "width": width, "height": height }));
+            }
+        }
+
+        if let Some(code_version) = &config.code_version {
+            javascript.insert("code_version".into(), code_version.clone().into());
+        }
+
+        javascript.insert(
+            "source_map_enabled".into(),
+            config.source_map_enabled.into(),
+        );
+        javascript.insert(
+            "guess_uncaught_frames".into(),
+            config.guess_uncaught_frames.into(),
+        );
+
+        item.data
+            .extra
+            .entry("client".into())
+            .or_insert_with(|| json!({ "javascript": javascript }));
+
+        let location = window.location();
+
+        if let Ok(url) = location.href() {
+            let mut request = Map::new();
+
+            request.insert("url".into(), url.into());
+
+            if let Ok(query_string) = location.search() {
+                request.insert(
+                    "query_string".into(),
+                    query_string.trim_start_matches('?').into(),
+                );
+            }
+
+            item.data
+                .extra
+                .entry("request".into())
+                .or_insert(Value::Object(request));
+        }
+
+        item.data
+            .extra
+            .entry("platform".into())
+            .or_insert_with(|| "browser".into());
+        item.data
+            .language
+            .get_or_insert_with(|| "javascript".into());
+    }
+
+    /// Pending items would be lost with the page, so they're handed to the
+    /// browser to send once the page is hidden or unloaded.
+    fn listen_for_unload(&self) -> Result<(), JsValue> {
+        let window = web_sys::window().ok_or_else(|| JsValue::from("no global `window` exists"))?;
+
+        let document = match window.document() {
+            Some(document) => document,
+            None => return Ok(()),
+        };
+
+        let transport = self.transport.clone();
+        let hidden = document.clone();
+
+        let on_visibility_change = Closure::wrap(Box::new(move || {
+            let state = js_sys::Reflect::get(&hidden, &"visibilityState".into())
+                .ok()
+                .and_then(|state| state.as_string());
+
+            if state.as_deref() == Some("hidden") {
+                send_pending(&transport);
+            }
+        }) as Box<dyn FnMut()>);
+
+        document.add_event_listener_with_callback(
+            "visibilitychange",
+            on_visibility_change.as_ref().unchecked_ref(),
+        )?;
+
+        on_visibility_change.forget();
+
+        let transport = self.transport.clone();
+
+        add_window_listener(
+            "pagehide",
+            Closure::wrap(Box::new(move || send_pending(&transport)) as Box<dyn FnMut()>),
+        )
     }
 
-    pub fn log(&self, level: Level, message: &str, extra: JsValue) -> Result<(), JsValue> {
-        let extra: Option<HashMap<String, Value>> = extra
-            .into_serde()
-            .map_err(|error| JsValue::from(format!("{}", error)))?;
+    fn is_ignored(&self, item: &Item) -> Result<bool, JsValue> {
+        let ignored = self.transport.is_ignored(item).map_err(js_error)?;
+
+        if ignored {
+            return Ok(true);
+        }
+
+        match &*self.check_ignore.borrow() {
+            Some(callback) => {
+                let item = to_js(item)?;
+
+                let ignored = callback.call1(&JsValue::NULL, &item)?.is_truthy();
+
+                if ignored {
+                    self.transport.count_ignored();
+                }
 
-        let item = Item::from((level, message, extra.unwrap_or_else(|| HashMap::new())));
+                Ok(ignored)
+            }
+            None => Ok(false),
+        }
+    }
 
-        self.transport.send(item);
+    /// Reports `message`, which may be a string or a thrown value. `Error`s
+    /// (and anything else with a `stack`) are sent as traces, following
+    /// their `cause`s.
+    ///
+    /// Returns a promise for the occurrence uuid; see `report`.
+    pub fn log(&self, level: Level, message: JsValue, extra: JsValue) -> js_sys::Promise {
+        let extra: Option<HashMap<String, Value>> = match serde_wasm_bindgen::from_value(extra) {
+            Ok(extra) => extra,
+            Err(error) => return js_sys::Promise::reject(&error.into()),
+        };
 
-        Ok(())
+        match item_from_value(level, message, extra.unwrap_or_default()) {
+            Ok(item) => self.report(item),
+            Err(error) => js_sys::Promise::reject(&error),
+        }
     }
 
-    pub fn debug(&self, message: &str, extra: JsValue) -> Result<(), JsValue> {
+    pub fn debug(&self, message: JsValue, extra: JsValue) -> js_sys::Promise {
         self.log(Level::Debug, message, extra)
     }
 
-    pub fn info(&self, message: &str, extra: JsValue) -> Result<(), JsValue> {
+    pub fn info(&self, message: JsValue, extra: JsValue) -> js_sys::Promise {
         self.log(Level::Info, message, extra)
     }
 
-    pub fn warning(&self, message: &str, extra: JsValue) -> Result<(), JsValue> {
+    pub fn warning(&self, message: JsValue, extra: JsValue) -> js_sys::Promise {
         self.log(Level::Warning, message, extra)
     }
 
-    pub fn error(&self, message: &str, extra: JsValue) -> Result<(), JsValue> {
+    pub fn error(&self, message: JsValue, extra: JsValue) -> js_sys::Promise {
         self.log(Level::Error, message, extra)
     }
 
-    pub fn critical(&self, message: &str, extra: JsValue) -> Result<(), JsValue> {
+    pub fn critical(&self, message: JsValue, extra: JsValue) -> js_sys::Promise {
         self.log(Level::Critical, message, extra)
     }
+
+    /// Returns counts of sent, failed, dropped and retried items, the queue
+    /// depth and the latest error.
+    pub fn stats(&self) -> Result<JsValue, JsValue> {
+        let stats = self.transport.stats().map_err(js_error)?;
+
+        to_js(&stats)
+    }
+
+    /// Returns the most recent delivery errors, oldest first.
+    #[wasm_bindgen(js_name = "recentErrors")]
+    pub fn recent_errors(&self) -> Result<JsValue, JsValue> {
+        let errors = self.transport.recent_errors().map_err(js_error)?;
+
+        to_js(&errors)
+    }
+
+    /// Resolves once every queued item has been sent.
+    pub fn flush(&self) -> js_sys::Promise {
+        let transport = self.transport.clone();
+
+        future_to_promise(async move {
+            transport.flush().await.map_err(js_error)?;
+
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Sends every queued item, then stops the instance. Rejects if any item
+    /// that nobody was waiting on failed.
+    pub fn shutdown(&self) -> js_sys::Promise {
+        let transport = self.transport.clone();
+
+        future_to_promise(async move {
+            transport.close().await.map_err(js_error)?;
+
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+}
+
+/// A JS function registered as a transport hook. Without the `atomics`
+/// target feature there is only one thread on wasm, so it's never actually
+/// sent or shared. Builds with threads have no such guarantee and don't get
+/// the impls, so hooks fail to compile there instead of calling into another
+/// thread's JS.
+struct Callback(js_sys::Function);
+
+#[cfg(not(target_feature = "atomics"))]
+unsafe impl Send for Callback {}
+#[cfg(not(target_feature = "atomics"))]
+unsafe impl Sync for Callback {}
+
+/// An `Error` with a `code` property naming the kind of failure.
+fn js_error(error: Error) -> JsValue {
+    js_error_ref(&error)
+}
+
+/// Also carries the `status` and `body` of the response if Rollbar refused
+/// the item.
+fn js_error_ref(error: &Error) -> JsValue {
+    let js_error = js_sys::Error::new(&error.to_string());
+
+    let _ = js_sys::Reflect::set(&js_error, &"code".into(), &error.code().into());
+
+    if let Some(rejection) = error.rejection() {
+        let _ = js_sys::Reflect::set(&js_error, &"status".into(), &rejection.status.into());
+        let _ = js_sys::Reflect::set(&js_error, &"body".into(), &rejection.body.as_str().into());
+    }
+
+    js_error.into()
+}
+
+/// Converts `value` to plain objects, as JSON would, so maps don't come out
+/// as `Map`s and missing fields come out as `null`.
+fn to_js(value: &impl serde::Serialize) -> Result<JsValue, JsValue> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(JsValue::from)
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = String)]
+    fn to_string(value: &JsValue) -> String;
+}
+
+/// Listeners stay installed for the life of the page, so the closure is
+/// leaked rather than kept alive by the instance.
+fn add_window_listener<T: ?Sized + wasm_bindgen::closure::WasmClosure>(
+    event: &str,
+    listener: Closure<T>,
+) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from("no global `window` exists"))?;
+
+    window.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())?;
+
+    listener.forget();
+
+    Ok(())
+}
+
+/// Sends every pending item with `navigator.sendBeacon`, falling back to a
+/// `keepalive` fetch when the beacon is refused. Neither can set headers, so
+/// the access token goes in the body, which is sent as `text/plain` to avoid
+/// a CORS preflight.
+fn send_pending(transport: &Transport) {
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let pending = match transport.take_pending() {
+        Ok(pending) => pending,
+        Err(error) => {
+            web_sys::console::error_1(&format!("unable to send pending items: {}", error).into());
+            return;
+        }
+    };
+
+    let endpoint = transport.endpoint();
+
+    for item in pending {
+        let body = match transport.body_with_token(&item) {
+            Ok(body) => body,
+            Err(error) => {
+                web_sys::console::error_1(
+                    &format!("unable to send pending item: {}", error).into(),
+                );
+                continue;
+            }
+        };
+
+        let queued = window
+            .navigator()
+            .send_beacon_with_opt_str(&endpoint, Some(&body))
+            .unwrap_or(false);
+
+        if !queued {
+            if let Err(error) = fetch_keepalive(&window, &endpoint, &body) {
+                web_sys::console::error_2(&"unable to send pending item:".into(), &error);
+            }
+        }
+    }
+}
+
+fn fetch_keepalive(window: &web_sys::Window, url: &str, body: &str) -> Result<(), JsValue> {
+    let init = js_sys::Object::new();
+
+    js_sys::Reflect::set(&init, &"method".into(), &"POST".into())?;
+    js_sys::Reflect::set(&init, &"body".into(), &body.into())?;
+    js_sys::Reflect::set(&init, &"keepalive".into(), &true.into())?;
+
+    let fetch: js_sys::Function = js_sys::Reflect::get(window, &"fetch".into())?.dyn_into()?;
+
+    fetch.call2(window, &url.into(), &init)?;
+
+    Ok(())
+}
+
+/// Builds a trace item from a thrown value, or a message item from anything
+/// else.
+fn item_from_value(
+    level: Level,
+    message: JsValue,
+    extra: HashMap<String, Value>,
+) -> Result<Item, JsValue> {
+    if is_error(&message)? {
+        let traces = stack::chain(message, read_error)?;
+
+        Ok(Item::from_traces(level, traces, extra))
+    } else {
+        Ok(Item::from((level, to_string(&message), extra)))
+    }
+}
+
+/// Uses the error the event carries when there is one. Errors thrown by
+/// cross-origin scripts only come with a message and position, which are
+/// reported as a single frame.
+fn item_from_error_event(event: &ErrorEvent) -> Result<Item, JsValue> {
+    let error = event.error();
+
+    if is_error(&error)? {
+        return item_from_value(Level::Error, error, HashMap::new());
+    }
+
+    let frame = Frame::new(
+        event.filename(),
+        Some(event.lineno()).filter(|lineno| *lineno > 0),
+        Some(event.colno()).filter(|colno| *colno > 0),
+        None,
+    );
+
+    let message = Some(event.message()).filter(|message| !message.is_empty());
+
+    let trace = Trace::new(vec![frame], Exception::new("Error", message));
+
+    Ok(Item::from_traces(Level::Error, vec![trace], HashMap::new()))
+}
+
+/// Whether `value` should be reported as a trace: an `Error`, or any object
+/// with a `stack` such as errors from another realm.
+fn is_error(value: &JsValue) -> Result<bool, JsValue> {
+    if value.is_instance_of::<js_sys::Error>() {
+        return Ok(true);
+    }
+
+    if !value.is_object() {
+        return Ok(false);
+    }
+
+    Ok(js_sys::Reflect::get(value, &"stack".into())?.is_string())
+}
+
+/// Reads the trace of an error and its `cause`. Anything thrown that isn't
+/// an object becomes a plain `Error` with its string form as the message.
+fn read_error(error: JsValue) -> Result<(Trace, Option<JsValue>), JsValue> {
+    if !error.is_object() {
+        return Ok((stack::trace("Error", &to_string(&error), None), None));
+    }
+
+    let name = js_sys::Reflect::get(&error, &"name".into())?.as_string();
+    let message = js_sys::Reflect::get(&error, &"message".into())?.as_string();
+    let stack = js_sys::Reflect::get(&error, &"stack".into())?.as_string();
+
+    let cause = js_sys::Reflect::get(&error, &"cause".into())?;
+    let cause = if cause.is_undefined() || cause.is_null() {
+        None
+    } else {
+        Some(cause)
+    };
+
+    let trace = stack::trace(
+        name.as_deref().unwrap_or_default(),
+        message.as_deref().unwrap_or_default(),
+        stack.as_deref(),
+    );
+
+    Ok((trace, cause))
 }